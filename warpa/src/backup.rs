@@ -0,0 +1,152 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+
+/// A copy of an archive taken before it is modified, moved in as the newest backup
+/// once the modification succeeds.
+pub struct Backup {
+    archive: PathBuf,
+    pending: PathBuf,
+    committed: bool,
+}
+
+impl Backup {
+    /// Keep the current content of the archive at `path`, if it exists.
+    ///
+    /// Archives are always replaced by a new file, so the current one is kept with a
+    /// hard link, or copied where links are not supported.
+    pub fn take(path: &Path) -> io::Result<Option<Self>> {
+        let archive = match fs::canonicalize(path) {
+            Ok(archive) => archive,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let pending = backup_path(&archive, 0);
+        if pending.exists() {
+            fs::remove_file(&pending)?;
+        }
+
+        if fs::hard_link(&archive, &pending).is_err() {
+            fs::copy(&archive, &pending)?;
+        }
+        debug!("Keeping {} as {}", archive.display(), pending.display());
+
+        Ok(Some(Self {
+            archive,
+            pending,
+            committed: false,
+        }))
+    }
+
+    /// Move the backup in as `.bak.1`, keeping at most `count` backups.
+    pub fn commit(mut self, count: usize) -> io::Result<()> {
+        let backups = list(&self.archive)?;
+        for (n, path) in backups.iter().rev() {
+            if *n >= count {
+                debug!("Removing old backup {}", path.display());
+                fs::remove_file(path)?;
+            } else {
+                fs::rename(path, backup_path(&self.archive, n + 1))?;
+            }
+        }
+
+        fs::rename(&self.pending, backup_path(&self.archive, 1))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for Backup {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Err(e) = fs::remove_file(&self.pending) {
+                warn!("Failed to remove {}: {e}", self.pending.display());
+            }
+        }
+    }
+}
+
+/// The path of the `n`th most recent backup of the `archive`.
+pub fn backup_path(archive: &Path, n: usize) -> PathBuf {
+    let mut name = archive.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{n}"));
+    archive.with_file_name(name)
+}
+
+/// The backups of the archive at `path` along with their number, most recent first.
+pub fn list(path: &Path) -> io::Result<Vec<(usize, PathBuf)>> {
+    let archive = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let prefix = format!(
+        "{}.bak.",
+        archive.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let dir = match archive.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let n = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|n| n.parse::<usize>().ok());
+
+        // The pending backup of a running command is not a backup yet.
+        if let Some(n @ 1..) = n {
+            backups.push((n, entry.path()));
+        }
+    }
+
+    backups.sort_by_key(|(n, _)| *n);
+    Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_rotate_backups() {
+        let dir = std::env::temp_dir().join("warpa-backup-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("a.rpa");
+
+        for version in ["1", "2", "3", "4"] {
+            // Replace the file like saving an archive does, keeping linked backups.
+            let _ = fs::remove_file(&archive);
+            fs::write(&archive, version).unwrap();
+            Backup::take(&archive).unwrap().unwrap().commit(2).unwrap();
+        }
+
+        let backups = list(&archive).unwrap();
+        let numbers = backups.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+        assert_eq!(numbers, [1, 2]);
+        assert_eq!(fs::read_to_string(&backups[0].1).unwrap(), "4");
+        assert_eq!(fs::read_to_string(&backups[1].1).unwrap(), "3");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_drop_uncommitted_backup() {
+        let dir = std::env::temp_dir().join("warpa-backup-drop-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("a.rpa");
+        fs::write(&archive, "1").unwrap();
+
+        drop(Backup::take(&archive).unwrap());
+        assert!(list(&archive).unwrap().is_empty());
+        assert!(!backup_path(&dir.canonicalize().unwrap().join("a.rpa"), 0).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
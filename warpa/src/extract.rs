@@ -1,99 +1,251 @@
 use std::{
-    fs::{self, File},
-    io::{Cursor, Read, Seek},
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::{self, Cursor},
+    mem,
     path::{Path, PathBuf},
 };
 
-use glob::Pattern;
-use log::info;
-use memmap2::Mmap;
-use rayon::prelude::ParallelIterator;
-use warpalib::{Content, ContentMap, RenpyArchive, RpaResult};
+use log::debug;
+use warpalib::{
+    free_path, ArchivePath, Content, ContentMap, ExtractOptions, ExtractSummary, MmapArchive,
+    OverwritePolicy, RenpyArchive, RpaError, RpaResult, SharedArchive,
+};
+
+use crate::is_stdio;
+
+/// Content of an archive mapped to the files they are extracted to.
+pub type Targets = Vec<(PathBuf, Content)>;
 
-/// An in-memory memmap archive backed by a file.
-pub struct MemArchive {
-    /// The file backing the memmap. Dropping this before the archive will lead to errors.
-    #[allow(dead_code)]
-    file: File,
+/// An archive opened for extraction.
+pub enum ExtractArchive {
+    /// Read using multiple threads from the file with positional reads.
+    File(SharedArchive),
 
-    /// The archive reading from the memmap.
-    pub archive: RenpyArchive<Cursor<Mmap>>,
+    /// Read using multiple threads from memory.
+    Memory(MmapArchive),
+
+    /// Read from stdin buffered into memory.
+    Stdin(RenpyArchive<Cursor<Vec<u8>>>),
 }
 
-impl MemArchive {
-    /// Read a file into memory and open an archive
-    pub fn open(path: &Path) -> RpaResult<MemArchive> {
-        let file = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
-        #[cfg(unix)]
-        mmap.advise(memmap2::Advice::WillNeed)?;
+impl ExtractArchive {
+    /// Open the archive, loading it into memory if `memory` is set.
+    pub fn open(path: &Path, memory: bool) -> RpaResult<Self> {
+        if is_stdio(path) {
+            Ok(ExtractArchive::Stdin(RenpyArchive::read_buffered(
+                io::stdin().lock(),
+            )?))
+        } else if memory {
+            Ok(ExtractArchive::Memory(MmapArchive::open(path)?))
+        } else {
+            Ok(ExtractArchive::File(SharedArchive::open(path)?))
+        }
+    }
 
-        let archive = RenpyArchive::read(Cursor::new(mmap))?;
-        Ok(MemArchive { file, archive })
+    /// Take the content out of the archive, leaving it empty.
+    pub fn take_content(&mut self) -> ContentMap {
+        match self {
+            ExtractArchive::File(archive) => mem::take(&mut archive.content),
+            ExtractArchive::Memory(archive) => mem::take(&mut archive.content),
+            ExtractArchive::Stdin(archive) => mem::take(&mut archive.content),
+        }
     }
-}
 
-pub fn filter_content<'a>(
-    content: ContentMap,
-    files: &'a [PathBuf],
-    pattern: Option<&'a Pattern>,
-) -> Box<dyn Iterator<Item = (PathBuf, Content)> + 'a> {
-    match (files, pattern) {
-        (f, Some(pattern)) if f.is_empty() => Box::new(
-            content
-                .into_iter()
-                .filter(|(path, _)| pattern.matches_path(path)),
-        ),
-        (f, Some(pattern)) => Box::new(
-            content
-                .into_iter()
-                .filter(|(path, _)| pattern.matches_path(path) || f.contains(path)),
-        ),
-        (f, None) if f.is_empty() => Box::new(content.into_iter()),
-        (f, None) => Box::new(content.into_iter().filter(|(path, _)| f.contains(path))),
+    /// Extract the `targets` read from this archive into `dir`.
+    ///
+    /// Targets outside of `dir` are extracted to their absolute path.
+    pub fn extract(
+        self,
+        dir: &Path,
+        targets: Targets,
+        options: &ExtractOptions,
+    ) -> RpaResult<ExtractSummary> {
+        let content = targets
+            .into_iter()
+            .map(|(target, content)| {
+                let path = target.strip_prefix(dir).unwrap_or(&target);
+                Ok((ArchivePath::from_path(path)?, content))
+            })
+            .collect::<RpaResult<HashMap<_, _>>>()?
+            .into();
+
+        match self {
+            ExtractArchive::File(mut archive) => {
+                archive.content = content;
+                Ok(archive.extract_to(dir, options))
+            }
+            ExtractArchive::Memory(mut archive) => {
+                archive.content = content;
+                Ok(archive.extract_to(dir, options))
+            }
+            ExtractArchive::Stdin(mut archive) => {
+                archive.content = content;
+                Ok(archive.extract_to(dir, options))
+            }
+        }
     }
 }
 
-pub fn extract_archive<'a, R: Seek + Read>(
-    reader: &mut R,
-    content_iter: Box<dyn Iterator<Item = (PathBuf, Content)> + 'a>,
-    out_dir: &Path,
-) -> RpaResult<()> {
-    for (output, content) in content_iter {
-        extract_content(reader, &output, &content, out_dir)?;
-    }
+/// Represents targets of multiple archives that would overwrite each other.
+pub enum Conflict {
+    /// The same target is extracted from two archives.
+    Shared {
+        target: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
 
-    Ok(())
+    /// Two targets differ only by case and collide on case-insensitive filesystems.
+    Case { first: PathBuf, second: PathBuf },
 }
 
-pub fn extract_archive_threaded<'p, P>(reader: Mmap, content: P, out_dir: &Path) -> RpaResult<()>
-where
-    P: ParallelIterator<Item = (&'p PathBuf, &'p Content)>,
-{
-    content
-        .map_init(
-            || Cursor::new(&reader),
-            |reader, (output, content)| extract_content(reader, output, content, out_dir),
-        )
-        .collect::<RpaResult<()>>()
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::Shared {
+                target,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} is extracted from both {} and {}",
+                target.display(),
+                first.display(),
+                second.display()
+            ),
+            Conflict::Case { first, second } => write!(
+                f,
+                "{} and {} differ only by case",
+                first.display(),
+                second.display()
+            ),
+        }
+    }
 }
 
-pub fn extract_content<R: Seek + Read>(
-    reader: &mut R,
-    output: &Path,
-    content: &Content,
-    out_dir: &Path,
-) -> RpaResult<()> {
-    info!("Extracting {}...", output.display());
-
-    let output = out_dir.join(output);
-    if let Some(parent) = output.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)?;
+/// Resolve targets shared by multiple `archives` as if the archives were extracted
+/// one after another, and report the conflicts found.
+///
+/// # Errors
+///
+/// Returns an [`io::ErrorKind::AlreadyExists`] error on the first shared target if
+/// `policy` is [`OverwritePolicy::Error`].
+pub fn resolve_conflicts(
+    archives: &[PathBuf],
+    targets: &mut [Targets],
+    policy: OverwritePolicy,
+) -> RpaResult<Vec<Conflict>> {
+    let mut conflicts = Vec::new();
+    let mut owners: HashMap<PathBuf, (usize, usize)> = HashMap::new();
+    let mut cased: HashMap<String, PathBuf> = HashMap::new();
+    let mut removed = vec![HashSet::new(); targets.len()];
+
+    for i in 0..targets.len() {
+        for k in 0..targets[i].len() {
+            let target = targets[i][k].0.clone();
+
+            let lower = target.to_string_lossy().to_lowercase();
+            match cased.get(&lower) {
+                Some(other) if other != &target => conflicts.push(Conflict::Case {
+                    first: other.clone(),
+                    second: target.clone(),
+                }),
+                Some(_) => (),
+                None => {
+                    cased.insert(lower, target.clone());
+                }
+            }
+
+            let (j, l) = match owners.get(&target) {
+                Some(&owner) => owner,
+                None => {
+                    owners.insert(target, (i, k));
+                    continue;
+                }
+            };
+
+            let conflict = Conflict::Shared {
+                target: target.clone(),
+                first: archives[j].clone(),
+                second: archives[i].clone(),
+            };
+
+            match policy {
+                OverwritePolicy::Overwrite | OverwritePolicy::Different => {
+                    removed[j].insert(l);
+                    owners.insert(target, (i, k));
+                }
+                OverwritePolicy::Skip => {
+                    removed[i].insert(k);
+                }
+                OverwritePolicy::Error => {
+                    return Err(RpaError::Io(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        conflict.to_string(),
+                    )))
+                }
+                OverwritePolicy::Rename => {
+                    let renamed = free_path(&target, |p| owners.contains_key(p));
+                    debug!("Renaming {} to {}", target.display(), renamed.display());
+                    targets[i][k].0 = renamed.clone();
+                    owners.insert(renamed, (i, k));
+                }
+            }
+
+            conflicts.push(conflict);
         }
     }
 
-    let mut file = File::create(output)?;
-    content.copy_to(reader, &mut file)?;
-    Ok(())
+    for (targets, removed) in targets.iter_mut().zip(removed) {
+        let mut index = 0;
+        targets.retain(|_| {
+            index += 1;
+            !removed.contains(&(index - 1))
+        });
+    }
+
+    Ok(conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(paths: &[&str]) -> Targets {
+        paths
+            .iter()
+            .map(|p| (PathBuf::from(p), Content::Raw(vec![])))
+            .collect()
+    }
+
+    #[test]
+    fn should_resolve_shared_targets_in_archive_order() {
+        let archives = [PathBuf::from("a.rpa"), PathBuf::from("b.rpa")];
+
+        let mut overwrite = [targets(&["x.txt", "y.txt"]), targets(&["x.txt"])];
+        let conflicts =
+            resolve_conflicts(&archives, &mut overwrite, OverwritePolicy::Overwrite).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(overwrite[0].len(), 1);
+        assert_eq!(overwrite[1].len(), 1);
+
+        let mut skip = [targets(&["x.txt"]), targets(&["x.txt"])];
+        resolve_conflicts(&archives, &mut skip, OverwritePolicy::Skip).unwrap();
+        assert_eq!(skip[0].len(), 1);
+        assert!(skip[1].is_empty());
+
+        let mut error = [targets(&["x.txt"]), targets(&["x.txt"])];
+        assert!(resolve_conflicts(&archives, &mut error, OverwritePolicy::Error).is_err());
+    }
+
+    #[test]
+    fn should_report_case_conflicts() {
+        let archives = [PathBuf::from("a.rpa")];
+        let mut cased = [targets(&["Image.png", "image.png"])];
+
+        let conflicts =
+            resolve_conflicts(&archives, &mut cased, OverwritePolicy::Overwrite).unwrap();
+        assert!(matches!(conflicts[..], [Conflict::Case { .. }]));
+    }
 }
@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use clap::Args;
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+use warpalib::{ArchivePath, Content, ContentMap, RpaError, RpaResult};
+
+use crate::types::ByteSize;
+
+/// Options selecting files of an archive, shared by commands reading archive content.
+#[derive(Args, Debug, Default)]
+pub struct FilterArgs {
+    /// Select files matching this glob pattern. Can be repeated.
+    #[arg(short, long = "pattern", visible_alias = "include")]
+    pub patterns: Vec<String>,
+
+    /// Skip files matching this glob pattern. Can be repeated.
+    #[arg(short = 'x', long = "exclude")]
+    pub excludes: Vec<String>,
+
+    /// Select files matching this regular expression. Can be repeated.
+    #[arg(long = "regex")]
+    pub regexes: Vec<String>,
+
+    /// Only select files with one of these extensions (e.g. png,jpg).
+    #[arg(short, long = "ext", value_delimiter = ',')]
+    pub extensions: Vec<String>,
+
+    /// Only select files larger than this size (e.g. 512K).
+    #[arg(long)]
+    pub larger_than: Option<ByteSize>,
+
+    /// Only select files smaller than this size (e.g. 4M).
+    #[arg(long)]
+    pub smaller_than: Option<ByteSize>,
+
+    /// Select the paths listed in this file, one per line. Use `-` to read from stdin.
+    #[arg(long)]
+    pub from_file: Option<PathBuf>,
+
+    /// Match paths, patterns and regular expressions ignoring case, the way renpy
+    /// resolves names.
+    #[arg(short, long)]
+    pub ignore_case: bool,
+}
+
+impl FilterArgs {
+    /// Compile the options together with the literal `files` given to a command.
+    pub fn build(self, files: Vec<ArchivePath>) -> RpaResult<Filter> {
+        let mut paths = files;
+        if let Some(path) = self.from_file {
+            paths.extend(read_paths(&path)?);
+        }
+
+        let regexes = self
+            .regexes
+            .iter()
+            .map(|s| {
+                RegexBuilder::new(s)
+                    .case_insensitive(self.ignore_case)
+                    .build()
+                    .map_err(|e| {
+                        RpaError::Io(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+                    })
+            })
+            .collect::<RpaResult<_>>()?;
+
+        Ok(Filter {
+            folded: match self.ignore_case {
+                true => paths.iter().map(ArchivePath::fold).collect(),
+                false => HashSet::new(),
+            },
+            paths: paths.into_iter().collect(),
+            patterns: compile(&self.patterns)?,
+            excludes: compile(&self.excludes)?,
+            regexes,
+            extensions: self
+                .extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect(),
+            larger_than: self.larger_than.map(|s| s.0),
+            smaller_than: self.smaller_than.map(|s| s.0),
+            ignore_case: self.ignore_case,
+        })
+    }
+}
+
+/// A compiled selection of archive files.
+///
+/// A file is selected when it matches any of the literal paths, include patterns or
+/// regular expressions (or when none are given), passes the extension and size limits
+/// and matches none of the exclude patterns.
+#[derive(Debug, Default)]
+pub struct Filter {
+    paths: HashSet<ArchivePath>,
+    folded: HashSet<String>,
+    patterns: Vec<Pattern>,
+    excludes: Vec<Pattern>,
+    regexes: Vec<Regex>,
+    extensions: Vec<String>,
+    larger_than: Option<u64>,
+    smaller_than: Option<u64>,
+    ignore_case: bool,
+}
+
+impl Filter {
+    /// Whether the filter selects every file.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+            && self.patterns.is_empty()
+            && self.excludes.is_empty()
+            && self.regexes.is_empty()
+            && self.extensions.is_empty()
+            && self.larger_than.is_none()
+            && self.smaller_than.is_none()
+    }
+
+    /// Whether the file at `path` with `content` is selected.
+    pub fn matches(&self, path: &ArchivePath, content: &Content) -> bool {
+        let options = MatchOptions {
+            case_sensitive: !self.ignore_case,
+            ..MatchOptions::new()
+        };
+        let literal = match self.ignore_case {
+            true => self.folded.contains(&path.fold()),
+            false => self.paths.contains(path),
+        };
+
+        let included =
+            (self.paths.is_empty() && self.patterns.is_empty() && self.regexes.is_empty())
+                || literal
+                || self
+                    .patterns
+                    .iter()
+                    .any(|p| p.matches_with(path.as_str(), options))
+                || self.regexes.iter().any(|r| r.is_match(path.as_str()));
+
+        let excluded = self
+            .excludes
+            .iter()
+            .any(|p| p.matches_with(path.as_str(), options));
+        if !included || excluded {
+            return false;
+        }
+
+        if !self.extensions.is_empty() {
+            let name = path.as_str().rsplit('/').next().unwrap_or_default();
+            let extension = name
+                .rsplit_once('.')
+                .map(|(_, e)| e.to_lowercase())
+                .unwrap_or_default();
+            if !self.extensions.contains(&extension) {
+                return false;
+            }
+        }
+
+        if self.larger_than.is_some() || self.smaller_than.is_some() {
+            let length = match content.length() {
+                Ok(length) => length,
+                Err(_) => return false,
+            };
+            if self.larger_than.is_some_and(|min| length <= min)
+                || self.smaller_than.is_some_and(|max| length >= max)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Keep only the selected files of `content`.
+    pub fn apply(&self, content: ContentMap) -> ContentMap {
+        content
+            .into_iter()
+            .filter(|(path, content)| self.matches(path, content))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    /// The literal paths selected by the filter.
+    pub fn paths(&self) -> impl Iterator<Item = &ArchivePath> {
+        self.paths.iter()
+    }
+
+    /// Whether the literal `path` is in `content`, ignoring case if the filter does.
+    pub fn exists(&self, path: &ArchivePath, content: &ContentMap) -> bool {
+        match self.ignore_case {
+            true => content.get_ignore_case(path).is_some(),
+            false => content.contains_key(path),
+        }
+    }
+
+    /// Literal paths for which `exists` returns false.
+    pub fn missing<F: Fn(&ArchivePath) -> bool>(&self, exists: F) -> Vec<&ArchivePath> {
+        let mut missing = self.paths.iter().filter(|p| !exists(p)).collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+}
+
+fn compile(patterns: &[String]) -> RpaResult<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|s| Pattern::from_str(s).map_err(RpaError::from))
+        .collect()
+}
+
+/// Read archive paths listed one per line, skipping blank lines and `#` comments.
+fn read_paths(path: &PathBuf) -> io::Result<Vec<ArchivePath>> {
+    let reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            paths.push(ArchivePath::from(line));
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(length: usize) -> Content {
+        Content::Raw(vec![0u8; length])
+    }
+
+    #[test]
+    fn should_select_everything_when_empty() {
+        let filter = FilterArgs::default().build(vec![]).unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&ArchivePath::from("any/file.txt"), &raw(0)));
+    }
+
+    #[test]
+    fn should_combine_includes_and_excludes() {
+        let args = FilterArgs {
+            patterns: vec!["images/**".to_string()],
+            excludes: vec!["**/*.webp".to_string()],
+            regexes: vec![r"\.rpy$".to_string()],
+            ..Default::default()
+        };
+        let filter = args.build(vec![ArchivePath::from("readme.txt")]).unwrap();
+
+        assert!(filter.matches(&ArchivePath::from("images/bg.png"), &raw(0)));
+        assert!(filter.matches(&ArchivePath::from("script.rpy"), &raw(0)));
+        assert!(filter.matches(&ArchivePath::from("readme.txt"), &raw(0)));
+        assert!(!filter.matches(&ArchivePath::from("images/bg.webp"), &raw(0)));
+        assert!(!filter.matches(&ArchivePath::from("audio/theme.ogg"), &raw(0)));
+    }
+
+    #[test]
+    fn should_match_ignoring_case() {
+        let args = FilterArgs {
+            patterns: vec!["Images/*.PNG".to_string()],
+            excludes: vec!["**/SIDE.png".to_string()],
+            ignore_case: true,
+            ..Default::default()
+        };
+        let filter = args.build(vec![ArchivePath::from("Script.RPY")]).unwrap();
+
+        assert!(filter.matches(&ArchivePath::from("script.rpy"), &raw(0)));
+        assert!(filter.matches(&ArchivePath::from("images/bg.png"), &raw(0)));
+        assert!(!filter.matches(&ArchivePath::from("images/side.png"), &raw(0)));
+
+        let mut content = ContentMap::default();
+        content.insert_raw("script.rpy", vec![]);
+        assert!(filter.exists(&ArchivePath::from("Script.RPY"), &content));
+    }
+
+    #[test]
+    fn should_limit_extensions_and_sizes() {
+        let args = FilterArgs {
+            extensions: vec![".PNG".to_string(), "jpg".to_string()],
+            larger_than: Some(ByteSize(2)),
+            smaller_than: Some(ByteSize(8)),
+            ..Default::default()
+        };
+        let filter = args.build(vec![]).unwrap();
+
+        assert!(filter.matches(&ArchivePath::from("bg.png"), &raw(4)));
+        assert!(!filter.matches(&ArchivePath::from("bg.png"), &raw(2)));
+        assert!(!filter.matches(&ArchivePath::from("bg.png"), &raw(8)));
+        assert!(!filter.matches(&ArchivePath::from("bg.webp"), &raw(4)));
+    }
+}
@@ -0,0 +1,21 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+    path::Path,
+};
+
+use warpalib::{dump_index, RawIndex, RpaResult};
+
+use crate::is_stdio;
+
+/// Read the deobfuscated index of the archive at `path`, or from stdin if `path` is `-`.
+pub fn dump(path: &Path) -> RpaResult<RawIndex> {
+    if is_stdio(path) {
+        let mut buffer = Vec::new();
+        io::stdin().lock().read_to_end(&mut buffer)?;
+        dump_index(&mut Cursor::new(buffer), "")
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        dump_index(&mut BufReader::new(File::open(path)?), &name)
+    }
+}
@@ -1,25 +1,40 @@
+mod backup;
 mod extract;
+mod filter;
+mod index;
+mod progress;
+mod serve;
 mod types;
 
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     fs::{self, File},
-    io::{BufRead, Seek},
+    io::{BufRead, Seek, Write},
     mem,
     path::{Path, PathBuf},
     process::exit,
     str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
 
+use backup::Backup;
 use clap::{Parser, Subcommand};
-use extract::{extract_archive, extract_archive_threaded, filter_content, MemArchive};
+use extract::{resolve_conflicts, ExtractArchive};
+use filter::{Filter, FilterArgs};
 use glob::{glob, Pattern};
 use log::{debug, error, info, warn};
+use progress::Progress;
 use rayon::prelude::*;
+use serve::Server;
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger};
 use std::io;
-use types::{HexKey, MappedPath, WriteVersion};
-use warpalib::{Content, RenpyArchive, RpaError, RpaResult};
+use types::{ByteSize, HexKey, MappedPath, MergeRule, OverwriteRule, WriteVersion};
+use warpalib::{
+    load_index, validate_target, ArchiveLock, ArchivePath, ArchiveSet, Content, ExtractOptions,
+    ExtractOutcome, Hooks, LockWait, OverwritePolicy, ParallelOptions, Provider, ReadOnce,
+    RenpyArchive, RpaError, RpaResult,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -40,6 +55,19 @@ struct Cli {
     #[arg(short, long)]
     override_version: bool,
 
+    /// Read files on this many threads while writing archives.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Seconds to wait for other runs modifying the same archive (default waits until
+    /// they finish, 0 fails immediately).
+    #[arg(long, value_name = "SECONDS")]
+    lock_timeout: Option<u64>,
+
+    /// Keep this many previous versions of modified archives as <ARCHIVE>.bak.N.
+    #[arg(short, long, value_name = "COUNT")]
+    backups: Option<usize>,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -48,20 +76,24 @@ struct Cli {
 enum Command {
     /// Add files to existing or create a new archive
     Add {
-        /// Path to existing or new archive file.
+        /// Path to existing or new archive file. Use `-` to write a new archive to stdout.
         path: PathBuf,
 
-        /// Mapped files to be added to the archive.
+        /// Mapped files to be added to the archive. Use `name=-` to read from stdin.
         files: Vec<MappedPath>,
 
         /// Add files matching this glob pattern.
         #[arg(short, long)]
         pattern: Option<String>,
+
+        /// Allow archive paths that could escape the directory they are extracted to.
+        #[arg(long)]
+        allow_unsafe: bool,
     },
 
     /// Extract files with full paths
     Extract {
-        /// Paths to archives to extract.
+        /// Paths to archives to extract. Use `-` to read an archive from stdin.
         archives: Vec<PathBuf>,
 
         /// Find archives using the glob pattern.
@@ -74,21 +106,69 @@ enum Command {
 
         /// Files to be extracted.
         #[arg(short, long)]
-        files: Vec<PathBuf>,
+        files: Vec<ArchivePath>,
 
-        /// Extract files matching the given glob pattern
-        #[arg(short, long)]
-        pattern: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
 
-        /// Load archive into memory and read using multiple threads. This is experimental.
+        /// Map archives into memory instead of reading them with positional reads.
         #[arg(short, long)]
         memory: bool,
+
+        /// What happens to existing files (overwrite, skip, error, different, rename).
+        #[arg(long, default_value_t)]
+        overwrite: OverwriteRule,
+
+        /// Only report files that conflict between archives without extracting.
+        #[arg(long)]
+        conflicts: bool,
+
+        /// Extract archive paths that could escape the output directory.
+        #[arg(long)]
+        allow_unsafe: bool,
+    },
+
+    /// Inspect or replace the index of an archive
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+
+    /// Write contents of files in an archive to stdout
+    Cat {
+        /// Path to archive. Use `-` to read from stdin.
+        archive: PathBuf,
+
+        /// Files to be written.
+        files: Vec<ArchivePath>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
     },
 
     /// List contents of archive
     List {
-        /// Path to archive.
+        /// Path to archive. Use `-` to read from stdin.
         archive: PathBuf,
+
+        /// Files to be listed.
+        files: Vec<ArchivePath>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Merge multiple archives into one
+    Merge {
+        /// Path to the output archive. Use `-` to write to stdout.
+        out: PathBuf,
+
+        /// Archives to be merged in order. Use `-` to read an archive from stdin.
+        archives: Vec<PathBuf>,
+
+        /// Content kept when archives share a path (last, first, error).
+        #[arg(short, long, default_value_t)]
+        duplicates: MergeRule,
     },
 
     /// Delete files from archive
@@ -97,28 +177,77 @@ enum Command {
         archive: PathBuf,
 
         /// Files to be deleted
-        files: Vec<PathBuf>,
+        files: Vec<ArchivePath>,
 
-        /// Remove archive files matching this glob pattern.
-        #[arg(short, long)]
-        pattern: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
 
-        /// Keep files matching the pattern.
+        /// Keep the selected files and remove the rest.
         #[arg(short, long)]
         keep: bool,
     },
 
+    /// Browse contents of archives using a local http server
+    Serve {
+        /// Paths to archives. Later archives shadow files in earlier archives.
+        archives: Vec<PathBuf>,
+
+        /// The address to listen on.
+        #[arg(short, long, default_value = "127.0.0.1:8000")]
+        address: String,
+    },
+
+    /// Roll an archive back to one of its backups
+    Restore {
+        /// Path to archive.
+        archive: PathBuf,
+
+        /// The backup to restore, 1 being the most recent.
+        #[arg(default_value_t = 1)]
+        backup: usize,
+
+        /// List the available backups instead of restoring.
+        #[arg(short, long)]
+        list: bool,
+    },
+
+    /// Split an archive into multiple archives
+    Split {
+        /// Path to archive.
+        archive: PathBuf,
+
+        /// Maximum size of each volume including its header and index (e.g. 512M, 4G).
+        #[arg(short, long)]
+        max_size: Option<ByteSize>,
+
+        /// Group files matching this glob pattern into separate volumes.
+        #[arg(short, long)]
+        pattern: Vec<String>,
+
+        /// Output directory. The default is archive directory.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Find which archive or file of a game directory provides a file
+    Which {
+        /// Path to the game directory.
+        dir: PathBuf,
+
+        /// Files to be resolved. All shadowed files are listed if none are given.
+        files: Vec<ArchivePath>,
+    },
+
     /// Update existing archive by reading from filesystem.
     Update {
         /// Path to archive.
         archive: PathBuf,
 
         /// Files in archive to be updated.
-        files: Vec<PathBuf>,
+        files: Vec<ArchivePath>,
 
-        /// Update archive files matching this glob pattern.
-        #[arg(short, long)]
-        pattern: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
 
         /// Find files relative to directory. The default is archive directory.
         #[arg(short, long)]
@@ -126,6 +255,28 @@ enum Command {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Write the deobfuscated index of an archive as json
+    Dump {
+        /// Path to archive. Use `-` to read from stdin.
+        archive: PathBuf,
+
+        /// Path to the json output. The default is stdout.
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Replace the index of an archive from json without touching its data
+    Load {
+        /// Path to archive.
+        archive: PathBuf,
+
+        /// Path to the json index. Use `-` to read from stdin.
+        index: PathBuf,
+    },
+}
+
 macro_rules! io_error {
     ($($arg:tt)*) => {
         Err(RpaError::Io(io::Error::new(io::ErrorKind::Other, format!($($arg)+))))
@@ -149,17 +300,34 @@ fn main() {
         _ => LevelFilter::Trace,
     };
 
+    // Logs go to stderr, stdout may carry archive data.
     TermLogger::init(
         level,
         Config::default(),
-        simplelog::TerminalMode::Mixed,
+        simplelog::TerminalMode::Stderr,
         ColorChoice::Auto,
     )
     .unwrap();
 
-    if let Err(e) = run(args) {
-        error!("{e}");
-        exit(1);
+    // Log lines would tear the progress bar apart.
+    let progress = (args.verbose == 0).then(|| Arc::new(Progress::new()));
+    let hooks = progress::hooks(progress.clone());
+
+    let result = run(args, hooks);
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    match result {
+        Ok(()) => (),
+        Err(RpaError::Cancelled) => {
+            error!("Cancelled.");
+            exit(130);
+        }
+        Err(e) => {
+            error!("{e}");
+            exit(1);
+        }
     }
 }
 
@@ -168,6 +336,10 @@ struct CliConfig {
     pub key: Option<HexKey>,
     pub write_version: Option<WriteVersion>,
     pub override_version: bool,
+    pub jobs: Option<usize>,
+    pub hooks: Hooks,
+    pub lock_wait: LockWait,
+    pub backups: Option<usize>,
 }
 
 impl CliConfig {
@@ -182,13 +354,85 @@ impl CliConfig {
             archive.key = Some(key.0);
         }
     }
+
+    /// Lock the archive at `path` for the rest of the command, unless it is stdout.
+    fn lock(&self, path: &Path) -> RpaResult<Option<ArchiveLock>> {
+        if is_stdio(path) {
+            return Ok(None);
+        }
+
+        match ArchiveLock::acquire(path, LockWait::FailFast) {
+            Err(RpaError::Locked(_)) if self.lock_wait != LockWait::FailFast => {
+                warn!("Waiting for another run modifying {}...", path.display());
+                ArchiveLock::acquire(path, self.lock_wait).map(Some)
+            }
+            result => result.map(Some),
+        }
+    }
+
+    /// Keep the archive at `path` before modifying it if backups are enabled.
+    fn backup(&self, path: &Path) -> RpaResult<Option<Backup>> {
+        match self.backups {
+            Some(_) => Ok(Backup::take(path)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Move the `backup` in once the archive was modified, rotating older backups.
+    fn commit_backup(&self, backup: Option<Backup>) -> RpaResult<()> {
+        if let (Some(backup), Some(count)) = (backup, self.backups) {
+            backup.commit(count)?;
+        }
+        Ok(())
+    }
+
+    /// Atomically write the archive to `path`, replacing any existing file and
+    /// keeping it as a backup if enabled.
+    ///
+    /// The archive is streamed to stdout instead if `path` is `-`.
+    fn replace_archive<R: Seek + BufRead>(
+        &self,
+        archive: RenpyArchive<R>,
+        path: &Path,
+    ) -> RpaResult<()> {
+        if is_stdio(path) {
+            debug!("Writing archive to stdout.");
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            archive.flush_stream_with(&mut stdout, &self.hooks)?;
+            return Ok(());
+        }
+
+        debug!("Replacing archive in {}.", path.display());
+
+        let backup = self.backup(path)?;
+        archive.save_atomic_with(path, |archive, file| match self.jobs {
+            Some(threads) => {
+                let options = ParallelOptions {
+                    threads,
+                    hooks: self.hooks.clone(),
+                    ..Default::default()
+                };
+                archive.flush_parallel(file, &options)
+            }
+            None => archive.flush_with(file, &self.hooks),
+        })?;
+        self.commit_backup(backup)
+    }
 }
 
-fn run(args: Cli) -> Result<(), RpaError> {
+fn run(args: Cli, hooks: Hooks) -> Result<(), RpaError> {
     let config = CliConfig {
         key: args.key,
         write_version: args.write_version,
         override_version: args.override_version,
+        jobs: args.jobs,
+        hooks,
+        lock_wait: match args.lock_timeout {
+            None => LockWait::Block,
+            Some(0) => LockWait::FailFast,
+            Some(seconds) => LockWait::Timeout(Duration::from_secs(seconds)),
+        },
+        backups: args.backups.filter(|count| *count > 0),
     };
 
     match args.command {
@@ -196,23 +440,40 @@ fn run(args: Cli) -> Result<(), RpaError> {
             path,
             files,
             pattern,
+            allow_unsafe,
         } => {
             fn add_files<R: Seek + BufRead>(
+                config: &CliConfig,
                 path: &Path,
                 files: Vec<MappedPath>,
                 pattern: Option<String>,
+                allow_unsafe: bool,
                 mut archive: RenpyArchive<R>,
-                temp_path: &Path,
             ) -> RpaResult<()> {
+                if files.iter().filter(|f| f.is_stdin()).count() > 1 {
+                    return io_error!("Only one file can be read from stdin.");
+                }
+
                 // Add manual specified files.
                 for file_map in files {
                     info!("Adding {}...", &file_map);
+                    let is_stdin = file_map.is_stdin();
                     let (archive_path, file_path) = file_map.into();
-                    let removed = archive
-                        .content
-                        .insert_file_mapped(archive_path.clone(), file_path);
+                    if !allow_unsafe {
+                        archive_path.validate()?;
+                    }
+
+                    let removed = if is_stdin {
+                        archive
+                            .content
+                            .insert_source(archive_path.clone(), ReadOnce::new(io::stdin()))
+                    } else {
+                        archive
+                            .content
+                            .insert_file_mapped(archive_path.clone(), file_path)
+                    };
                     if removed.is_some() {
-                        warn!("Removed previous content in {}.", archive_path.display());
+                        warn!("Removed previous content in {archive_path}.");
                     }
                 }
 
@@ -221,39 +482,55 @@ fn run(args: Cli) -> Result<(), RpaError> {
                     for file in glob(&pattern)? {
                         let file = file.expect("Failed glob iteration");
                         info!("Adding {}...", file.display());
-                        if archive.content.insert_file(file.clone()).is_some() {
+                        if !allow_unsafe {
+                            ArchivePath::from_path(&file)?.validate()?;
+                        }
+
+                        if archive.content.insert_file(file.clone())?.is_some() {
                             warn!("Removed previous content in {}.", file.display());
                         }
                     }
                 }
 
                 // Write and replace archive.
-                replace_archive(archive, path, temp_path)?;
+                config.replace_archive(archive, path)?;
 
                 Ok(())
             }
 
-            temp_scope(&path, |temp_path| {
-                if path.exists() && path.is_file() {
-                    let mut archive = RenpyArchive::open(&path)?;
-                    config.update_archive(&mut archive);
-                    add_files(&path, files, pattern, archive, temp_path)
-                } else if path.exists() {
-                    io_error!("Expected an archive or empty path: {}", path.display())
-                } else {
-                    let mut archive = RenpyArchive::new();
-                    config.update_archive(&mut archive);
-                    add_files(&path, files, pattern, archive, temp_path)
+            if is_stdio(&path) {
+                if files.iter().any(|f| f.is_stdin()) {
+                    return io_error!("Files cannot be read from stdin while writing to stdout.");
                 }
-            })
+
+                let mut archive = RenpyArchive::new();
+                config.update_archive(&mut archive);
+                return add_files(&config, &path, files, pattern, allow_unsafe, archive);
+            }
+
+            let _lock = config.lock(&path)?;
+            if path.exists() && path.is_file() {
+                let mut archive = RenpyArchive::open(&path)?;
+                config.update_archive(&mut archive);
+                add_files(&config, &path, files, pattern, allow_unsafe, archive)
+            } else if path.exists() {
+                io_error!("Expected an archive or empty path: {}", path.display())
+            } else {
+                let mut archive = RenpyArchive::new();
+                config.update_archive(&mut archive);
+                add_files(&config, &path, files, pattern, allow_unsafe, archive)
+            }
         }
         Command::Extract {
             mut archives,
             archive_pattern: archives_pattern,
             out,
             files,
-            pattern,
+            filter,
             memory,
+            overwrite,
+            conflicts,
+            allow_unsafe,
         } => {
             if let Some(pattern) = archives_pattern {
                 info!("Adding archives from glob pattern '{}'...", pattern);
@@ -263,98 +540,373 @@ fn run(args: Cli) -> Result<(), RpaError> {
                 }
             }
 
-            archives
-                .into_par_iter()
+            if archives.iter().filter(|a| is_stdio(a)).count() > 1 {
+                return io_error!("Only one archive can be read from stdin.");
+            }
+
+            let filter = filter.build(files)?;
+
+            // Read all indexes first so that conflicts between archives can be resolved.
+            let opened = archives
+                .par_iter()
                 .map(|path| {
-                    let out_dir = get_out_or_parent(out.as_ref(), &path)?;
-
-                    let pattern = pattern
-                        .as_ref()
-                        .map(|s| Pattern::from_str(s))
-                        .map_or(Ok(None), |r| r.map(Some))?;
-
-                    if memory {
-                        let mmap = MemArchive::open(&path)?;
-                        if files.is_empty() && pattern.is_none() {
-                            // Convert the map into a parralel iter skipping iter collection.
-                            extract_archive_threaded(
-                                mmap.archive.reader.into_inner(),
-                                mmap.archive.content.par_iter(),
-                                out_dir,
-                            )
-                        } else {
-                            // Filter and collect results so parallelization will be affective.
-                            let content =
-                                filter_content(mmap.archive.content, &files, pattern.as_ref())
-                                    .collect::<Vec<_>>();
-
-                            extract_archive_threaded(
-                                mmap.archive.reader.into_inner(),
-                                content.par_iter().map(|(p, c)| (p, c)),
-                                out_dir,
-                            )
+                    let out_dir = get_out_or_parent(out.as_ref(), path)?;
+                    let mut archive = ExtractArchive::open(path, memory)?;
+                    let content = archive.take_content();
+                    let found = filter
+                        .paths()
+                        .filter(|p| filter.exists(p, &content))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let content = filter.apply(content);
+
+                    if !allow_unsafe {
+                        let unsafe_count = content
+                            .keys()
+                            .filter_map(|path| {
+                                path.validate()
+                                    .and_then(|_| validate_target(out_dir, &path.to_path()))
+                                    .err()
+                            })
+                            .inspect(|e| error!("{e}"))
+                            .count();
+
+                        if unsafe_count > 0 {
+                            return io_error!(
+                                "{} contains {unsafe_count} unsafe paths. Use --allow-unsafe to extract them.",
+                                path.display()
+                            );
                         }
-                    } else {
-                        let mut archive = RenpyArchive::open(&path)?;
-                        let content_iter =
-                            filter_content(archive.content, &files, pattern.as_ref());
-                        extract_archive(&mut archive.reader, content_iter, out_dir)
                     }
+
+                    let targets = content
+                        .into_iter()
+                        .map(|(path, content)| (out_dir.join(path.to_path()), content))
+                        .collect::<Vec<_>>();
+                    Ok((archive, out_dir, targets, found))
                 })
-                .collect::<RpaResult<()>>()
+                .collect::<RpaResult<Vec<_>>>()?;
+
+            let mut found = HashSet::new();
+            let mut targets = Vec::with_capacity(opened.len());
+            let mut archive_handles = Vec::with_capacity(opened.len());
+            for (archive, out_dir, archive_targets, archive_found) in opened {
+                archive_handles.push((archive, out_dir));
+                targets.push(archive_targets);
+                found.extend(archive_found);
+            }
+            let opened = archive_handles;
+            check_missing(&filter, |path| found.contains(path))?;
+
+            let policy = OverwritePolicy::from(&overwrite);
+            for conflict in resolve_conflicts(&archives, &mut targets, policy)? {
+                if conflicts {
+                    println!("{conflict}");
+                } else {
+                    warn!("{conflict}");
+                }
+            }
+
+            if conflicts {
+                return Ok(());
+            }
+
+            let options = ExtractOptions::new()
+                .with_overwrite(policy)
+                .with_allow_unsafe(allow_unsafe)
+                .with_hooks(config.hooks.clone())
+                .with_progress(|progress| match progress.outcome {
+                    ExtractOutcome::Written => {
+                        info!("Extracted {}", progress.target.display())
+                    }
+                    ExtractOutcome::Skipped => {
+                        info!("Skipped existing {}", progress.target.display())
+                    }
+                    ExtractOutcome::Failed(e) => error!("{}: {e}", progress.path),
+                });
+
+            // Each archive is extracted on multiple threads.
+            let mut failed = 0;
+            for ((archive, out_dir), targets) in opened.into_iter().zip(targets) {
+                let summary = archive.extract(out_dir, targets, &options)?;
+                if summary.cancelled {
+                    return Err(RpaError::Cancelled);
+                }
+                failed += summary.failed.len();
+            }
+
+            if failed > 0 {
+                return io_error!("Failed to extract {failed} files.");
+            }
+            Ok(())
         }
-        Command::List { archive } => {
-            let archive = RenpyArchive::open(&archive)?;
+        Command::Index { command } => match command {
+            IndexCommand::Dump { archive, out } => {
+                let index = index::dump(&archive)?;
+                match out {
+                    Some(out) => {
+                        let file = io::BufWriter::new(File::create(out)?);
+                        serde_json::to_writer_pretty(file, &index).map_err(io::Error::from)?;
+                    }
+                    None => {
+                        let mut stdout = io::stdout().lock();
+                        serde_json::to_writer_pretty(&mut stdout, &index)
+                            .map_err(io::Error::from)?;
+                        writeln!(stdout)?;
+                    }
+                }
+                Ok(())
+            }
+            IndexCommand::Load {
+                archive,
+                index: index_path,
+            } => {
+                let index = if is_stdio(&index_path) {
+                    serde_json::from_reader(io::stdin().lock())
+                } else {
+                    serde_json::from_reader(io::BufReader::new(File::open(&index_path)?))
+                }
+                .map_err(io::Error::from)?;
 
-            for path in archive.content.keys() {
-                println!("{}", path.display());
+                info!("Replacing index of {}...", archive.display());
+                let _lock = config.lock(&archive)?;
+                let backup = config.backup(&archive)?;
+                load_index(&archive, index, config.key.as_ref().map(|k| k.0))?;
+                config.commit_backup(backup)
+            }
+        },
+        Command::Cat {
+            archive,
+            files,
+            filter,
+        } => {
+            fn cat_files<R: Seek + BufRead>(
+                mut archive: RenpyArchive<R>,
+                filter: Filter,
+            ) -> RpaResult<()> {
+                check_missing(&filter, |path| filter.exists(path, &archive.content))?;
+
+                let content = filter.apply(mem::take(&mut archive.content));
+                let mut paths = content.keys().collect::<Vec<_>>();
+                paths.sort();
+
+                let mut stdout = io::stdout().lock();
+                for path in paths {
+                    debug!("Writing {path}...");
+                    content[path].copy_to(&mut archive.reader, &mut stdout)?;
+                }
+                stdout.flush()?;
+
+                Ok(())
+            }
+
+            let filter = filter.build(files)?;
+            if is_stdio(&archive) {
+                cat_files(RenpyArchive::read_buffered(io::stdin().lock())?, filter)
+            } else {
+                cat_files(RenpyArchive::open(&archive)?, filter)
+            }
+        }
+        Command::List {
+            archive,
+            files,
+            filter,
+        } => {
+            let filter = filter.build(files)?;
+            let content = if is_stdio(&archive) {
+                RenpyArchive::read_buffered(io::stdin().lock())?.content
+            } else {
+                RenpyArchive::open(&archive)?.content
+            };
+            check_missing(&filter, |path| filter.exists(path, &content))?;
+
+            for (path, content) in content.iter() {
+                if filter.matches(path, content) {
+                    println!("{path}");
+                }
             }
 
             Ok(())
         }
+        Command::Merge {
+            out,
+            archives,
+            duplicates,
+        } => {
+            if archives.iter().filter(|a| is_stdio(a)).count() > 1 {
+                return io_error!("Only one archive can be read from stdin.");
+            }
+
+            // Held while reading in case the output is also merged.
+            let _lock = config.lock(&out)?;
+            let mut archive = RenpyArchive::new();
+            config.update_archive(&mut archive);
+
+            for path in archives {
+                info!("Merging {}...", path.display());
+                let content = if is_stdio(&path) {
+                    // Nothing to point records at, keep their data in memory instead.
+                    let mut source = RenpyArchive::read_buffered(io::stdin().lock())?;
+                    for content in source.content.values_mut() {
+                        if let Content::Record(record) = content {
+                            let mut data = Vec::with_capacity(record.length as usize);
+                            record.copy_section(&mut source.reader, &mut data)?;
+                            *content = Content::Raw(data);
+                        }
+                    }
+                    source.content
+                } else {
+                    RenpyArchive::open(&path)?.content.into_external(&path)
+                };
+                archive.content.merge(content, (&duplicates).into())?;
+            }
+
+            config.replace_archive(archive, &out)
+        }
         Command::Remove {
             archive: archive_path,
             files,
-            pattern,
+            filter,
             keep,
         } => {
+            let filter = filter.build(files)?;
+            if filter.is_empty() {
+                return io_error!("Expected files or filters selecting files to remove.");
+            }
+
+            let _lock = config.lock(&archive_path)?;
             let mut archive = RenpyArchive::open(&archive_path)?;
             config.update_archive(&mut archive);
+            check_missing(&filter, |path| filter.exists(path, &archive.content))?;
 
-            for file in files {
-                info!("Removing {}...", file.display());
-                if archive.content.remove(file.as_path()).is_none() {
-                    return io_error!("File {} not found in the archive.", file.display());
+            archive.content.retain(|path, content| {
+                let keep = filter.matches(path, content) == keep;
+                if !keep {
+                    info!("Removing {path}...");
                 }
+                keep
+            });
+
+            config.replace_archive(archive, &archive_path)
+        }
+        Command::Serve { archives, address } => {
+            let server = Server::bind(&address, &archives)?;
+            println!("Serving on http://{}", server.local_addr()?);
+            server.run()
+        }
+        Command::Restore {
+            archive: archive_path,
+            backup: number,
+            list,
+        } => {
+            // Backups are rotated by runs holding the lock, list them under it.
+            let _lock = config.lock(&archive_path)?;
+            let backups = backup::list(&archive_path)?;
+
+            if list {
+                for (number, path) in backups {
+                    let metadata = fs::metadata(&path)?;
+                    let age = metadata
+                        .modified()?
+                        .elapsed()
+                        .map_or(0, |elapsed| elapsed.as_secs());
+                    println!(
+                        "{number}\t{}\t{} bytes\t{}",
+                        format_age(age),
+                        metadata.len(),
+                        path.display()
+                    );
+                }
+                return Ok(());
             }
 
-            if let Some(pattern_str) = pattern {
-                let pattern = Pattern::from_str(&pattern_str)?;
+            let Some((_, backup_path)) = backups.into_iter().find(|(n, _)| *n == number) else {
+                return not_found!(
+                    "backup {number} of {} not found. Use --list to show backups.",
+                    archive_path.display()
+                );
+            };
+
+            info!("Restoring {}...", backup_path.display());
 
-                let content = mem::take(&mut archive.content);
-                archive.content = content
-                    .into_iter()
-                    .filter(move |(path, _)| {
-                        let keep = pattern.matches_path(path) ^ keep;
-                        if !keep {
-                            info!("Removing {}...", path.display());
-                        }
-                        keep
-                    })
-                    .collect::<HashMap<_, _>>()
-                    .into();
+            // Copy the backup as is rather than rewriting the archive.
+            let backup = config.backup(&archive_path)?;
+            RenpyArchive::open(&backup_path)?.save_atomic_with(&archive_path, |backup, file| {
+                let mut reader = backup.reader;
+                reader.rewind()?;
+                io::copy(&mut reader, file)?;
+                Ok(())
+            })?;
+            config.commit_backup(backup)
+        }
+        Command::Split {
+            archive: archive_path,
+            max_size,
+            pattern,
+            out,
+        } => {
+            if max_size.is_none() && pattern.is_empty() {
+                return io_error!("Expected a maximum size or patterns to split by.");
             }
 
-            temp_scope(&archive_path, |temp_path| {
-                replace_archive(archive, &archive_path, temp_path)
-            })
+            let out_dir = get_out_or_parent(out.as_ref(), &archive_path)?;
+            let stem = match archive_path.file_stem() {
+                Some(stem) => stem.to_string_lossy(),
+                None => return not_found!("unable to access archive name."),
+            };
+
+            let source = RenpyArchive::open(&archive_path)?;
+            let mut template = RenpyArchive::new();
+            template.version = source.version.clone();
+            template.key = source.key.or(template.key);
+            config.update_archive(&mut template);
+
+            let content = source.content.into_external(&archive_path);
+            let groups = if pattern.is_empty() {
+                vec![content]
+            } else {
+                let patterns = pattern
+                    .iter()
+                    .map(|s| Pattern::from_str(s))
+                    .collect::<Result<Vec<_>, _>>()?;
+                content.split_by_patterns(&patterns)
+            };
+
+            let mut volumes = Vec::new();
+            for group in groups.into_iter().filter(|g| !g.is_empty()) {
+                match max_size {
+                    Some(size) => volumes.extend(group.split_by_size(
+                        size.0,
+                        &template.version,
+                        template.key,
+                    )?),
+                    None => volumes.push(group),
+                }
+            }
+
+            for (index, volume) in volumes.into_iter().enumerate() {
+                let path = out_dir.join(format!("{}_{}.rpa", stem, index + 1));
+                info!("Writing {} ({} files)...", path.display(), volume.len());
+
+                let mut archive = RenpyArchive::new();
+                archive.version = template.version.clone();
+                archive.key = template.key;
+                archive.content = volume;
+
+                let _lock = config.lock(&path)?;
+                config.replace_archive(archive, &path)?;
+            }
+
+            Ok(())
         }
         Command::Update {
             archive: archive_path,
             files,
-            pattern,
+            filter,
             relative,
         } => {
+            let filter = filter.build(files)?;
+
             // Resolve the target directory and make sure its valid before reading archive.
             let dir = match relative.as_ref() {
                 None => match archive_path.parent() {
@@ -378,97 +930,111 @@ fn run(args: Cli) -> Result<(), RpaError> {
                 }
             };
 
+            let _lock = config.lock(&archive_path)?;
             let mut archive = RenpyArchive::open(&archive_path)?;
             config.update_archive(&mut archive);
+            check_missing(&filter, |path| filter.exists(path, &archive.content))?;
 
             // Update all if no specifics are defined.
-            if files.is_empty() && pattern.is_none() {
+            if filter.is_empty() {
                 debug!("Updating all files in archive, no specifics defined.");
-                archive.content = archive
-                    .content
-                    .into_iter()
-                    .map(|(path, _)| {
-                        let file = Content::File(dir.join(&path));
-                        info!("Updating {}...", path.display());
-                        (path, file)
-                    })
-                    .collect::<HashMap<_, _>>()
-                    .into();
-            } else {
-                debug!("Updating files defined by pattern in archive.");
-                if let Some(pattern) = pattern {
-                    let pattern = Pattern::from_str(&pattern)?;
-                    archive.content = archive
-                        .content
-                        .into_iter()
-                        .map(|(path, content)| {
-                            if pattern.matches_path(&path) {
-                                info!("Updating {}...", path.display());
-                                let file = Content::File(dir.join(&path));
-                                (path, file)
-                            } else {
-                                (path, content)
-                            }
-                        })
-                        .collect::<HashMap<_, _>>()
-                        .into();
+            }
+
+            // Only records stored in the archive are read back from the filesystem.
+            let selected = |path: &ArchivePath, content: &Content| {
+                matches!(content, Content::Record(_)) && filter.matches(path, content)
+            };
+
+            // Archive paths are joined to the directory files are read from, so unsafe
+            // paths could pack files from outside of it.
+            let unsafe_count = archive
+                .content
+                .iter()
+                .filter(|(path, content)| selected(path, content))
+                .filter_map(|(path, _)| {
+                    path.validate()
+                        .and_then(|_| validate_target(dir, &path.to_path()))
+                        .err()
+                })
+                .inspect(|e| error!("{e}"))
+                .count();
+
+            if unsafe_count > 0 {
+                return io_error!(
+                    "{} contains {unsafe_count} unsafe paths, refusing to update them.",
+                    archive_path.display()
+                );
+            }
+
+            for (path, content) in archive.content.iter_mut() {
+                if selected(path, content) {
+                    info!("Updating {path}...");
+                    *content = Content::File(dir.join(path.to_path()));
                 }
+            }
 
-                debug!("Updating files defined by path in archive.");
-                for path in files {
-                    match archive.content.get_mut(&path) {
-                        Some(content @ Content::Record(_)) => {
-                            info!("Updating {}...", path.display());
-                            *content = Content::File(dir.join(path))
-                        }
-                        Some(_) => (),
-                        None => {
-                            return io_error!("File not found in archive: '{}'", path.display())
-                        }
-                    }
+            config.replace_archive(archive, &archive_path)
+        }
+        Command::Which { dir, files } => {
+            let set = ArchiveSet::open(&dir)?;
+
+            if files.is_empty() {
+                for (path, providers) in set.shadowed()? {
+                    print_providers(&path, &providers);
                 }
+                return Ok(());
             }
 
-            temp_scope(&archive_path, |temp_path| {
-                replace_archive(archive, &archive_path, temp_path)
-            })
+            for path in files {
+                let providers = set.providers(&path);
+                if providers.is_empty() {
+                    return Err(RpaError::NotFound(path));
+                }
+                print_providers(&path, &providers);
+            }
+
+            Ok(())
         }
     }
 }
 
-fn replace_archive<R: Seek + BufRead>(
-    archive: RenpyArchive<R>,
-    path: &Path,
-    temp_path: &Path,
-) -> RpaResult<()> {
-    debug!("Replacing archive in {}.", path.display());
-
-    {
-        let mut temp_file = File::create(&temp_path)?;
-        archive.flush(&mut temp_file)?;
+/// Fail if files named explicitly in the `filter` do not pass `exists`.
+fn check_missing<F: Fn(&ArchivePath) -> bool>(filter: &Filter, exists: F) -> RpaResult<()> {
+    let missing = filter.missing(exists);
+    for path in missing.iter() {
+        error!("File not found in archive: '{path}'");
     }
 
-    fs::rename(temp_path, path)?;
-    Ok(())
+    match missing.len() {
+        0 => Ok(()),
+        count => not_found!("{count} files not found in archive."),
+    }
 }
 
-fn temp_scope<F>(path: &Path, f: F) -> RpaResult<()>
-where
-    F: FnOnce(&Path) -> RpaResult<()>,
-{
-    let mut temp_path = path.to_path_buf();
-    let mut temp_name = path.file_name().unwrap().to_os_string();
-    temp_name.push(".temp");
-    temp_path.set_file_name(temp_name);
-
-    let result = f(temp_path.as_path());
+fn print_providers(path: &ArchivePath, providers: &[Provider]) {
+    println!("{path}");
+    for (index, provider) in providers.iter().enumerate() {
+        if index == 0 {
+            println!("    {provider}");
+        } else {
+            println!("    {provider} [shadowed]");
+        }
+    }
+}
 
-    if temp_path.exists() {
-        warn!("Removing dangling {}.", temp_path.display());
-        fs::remove_file(&temp_path)?;
+/// Format an age in seconds using its largest unit.
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
     }
+}
 
-    result
+/// Whether `path` stands for stdin or stdout (`-`).
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
 }
 
 /// Returns [out] if given or [parent_of] other path.
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use log::warn;
+use warpalib::{ArchivePath, CancelToken, Hooks, ProgressObserver};
+
+/// Template of the progress bar drawn on stderr.
+const TEMPLATE: &str =
+    "{elapsed_precise} [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}";
+
+/// A progress bar of the bytes copied by archive operations.
+///
+/// Totals of consecutive operations add up, so a single bar covers commands handling
+/// multiple archives.
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    /// Create a progress bar drawn on stderr, hidden if stderr is not a terminal.
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(0);
+        bar.set_style(
+            ProgressStyle::with_template(TEMPLATE)
+                .expect("Invalid progress template")
+                .progress_chars("=> "),
+        );
+        Self { bar }
+    }
+
+    /// Remove the progress bar from the terminal.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+impl ProgressObserver for Progress {
+    fn started(&self, _entries: usize, bytes: u64) {
+        self.bar.inc_length(bytes);
+    }
+
+    fn entry_started(&self, path: &ArchivePath) {
+        self.bar.set_message(path.to_string());
+    }
+
+    fn bytes_copied(&self, bytes: u64) {
+        self.bar.inc(bytes);
+    }
+}
+
+/// Create hooks cancelled by Ctrl-C, reporting to the `progress` bar if given.
+///
+/// The first Ctrl-C lets the running operation stop and clean up after itself, a
+/// second one exits immediately.
+pub fn hooks(progress: Option<Arc<Progress>>) -> Hooks {
+    let token = CancelToken::new();
+    let handler_token = token.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() {
+            std::process::exit(130);
+        }
+        handler_token.cancel();
+    }) {
+        warn!("Failed to handle Ctrl-C: {e}");
+    }
+
+    let hooks = Hooks::new().with_cancel(token);
+    match progress {
+        Some(progress) => hooks.with_observer(progress),
+        None => hooks,
+    }
+}
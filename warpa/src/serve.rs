@@ -0,0 +1,474 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    ops::Range,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use warpalib::{ArchivePath, Content, ContentMap, MergePolicy, RenpyArchive, RpaResult};
+
+/// Number of connections answered at the same time.
+const WORKERS: usize = 8;
+
+/// Maximum length of the request line and headers.
+const MAX_HEAD_LENGTH: u64 = 8 * 1024;
+
+/// Time a client may stall sending or receiving before the connection is closed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A read-only http server browsing the contents of archives.
+pub struct Server {
+    listener: TcpListener,
+    content: Arc<ContentMap>,
+}
+
+impl Server {
+    /// Open the archives and bind the server to `address`.
+    ///
+    /// Content in later archives shadows content with the same path in earlier archives.
+    pub fn bind(address: &str, archives: &[PathBuf]) -> RpaResult<Server> {
+        let mut content = ContentMap::default();
+        for path in archives {
+            info!("Serving {}...", path.display());
+            let archive = RenpyArchive::open(path)?;
+            content.merge(archive.content.into_external(path), MergePolicy::LastWins)?;
+        }
+
+        let listener = TcpListener::bind(address)?;
+        Ok(Server {
+            listener,
+            content: Arc::new(content),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept and answer connections on a fixed number of workers until the process
+    /// is stopped.
+    pub fn run(self) -> RpaResult<()> {
+        let server = Arc::new(self);
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let server = Arc::clone(&server);
+                thread::spawn(move || server.work())
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            if worker.join().is_err() {
+                warn!("Server worker panicked");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Accept and answer connections one at a time.
+    fn work(&self) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept connection: {e}");
+                    continue;
+                }
+            };
+
+            if let Err(e) = handle(stream, &self.content) {
+                warn!("Failed to answer request: {e}");
+            }
+        }
+    }
+}
+
+/// Answer a single request on `stream` and close the connection.
+fn handle(mut stream: TcpStream, content: &ContentMap) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let (request_line, range) = match read_head(stream.try_clone()?)? {
+        Some(head) => head,
+        None => return write_status(&mut stream, "431 Request Header Fields Too Large"),
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return write_status(&mut stream, "400 Bad Request"),
+    };
+    debug!("Answering {method} {target}");
+
+    let head = match method {
+        "GET" => false,
+        "HEAD" => true,
+        _ => return write_status(&mut stream, "405 Method Not Allowed"),
+    };
+
+    let target = target.split('?').next().unwrap_or_default();
+    let path = match percent_decode(target.trim_start_matches('/')) {
+        Some(path) => path,
+        None => return write_status(&mut stream, "400 Bad Request"),
+    };
+
+    let path = ArchivePath::from(path).to_string();
+    if let Some(entry) = content.get(path.as_str()) {
+        return serve_entry(&mut stream, &path, entry, range.as_deref(), head);
+    }
+
+    // Not an entry, try listing it as a directory.
+    let prefix = if path.is_empty() || path.ends_with('/') {
+        path.clone()
+    } else {
+        format!("{path}/")
+    };
+
+    let mut children = BTreeSet::new();
+    for key in content.keys() {
+        if let Some(rest) = key.as_str().strip_prefix(&prefix) {
+            match rest.split_once('/') {
+                Some((dir, _)) => children.insert(format!("{dir}/")),
+                None => children.insert(rest.to_string()),
+            };
+        }
+    }
+
+    if children.is_empty() {
+        write_status(&mut stream, "404 Not Found")
+    } else if prefix != path {
+        let location = format!("/{}", percent_encode(&prefix));
+        write_head(
+            &mut stream,
+            "301 Moved Permanently",
+            &[("Location", location)],
+        )
+    } else {
+        let mut body = format!(
+            "<!DOCTYPE html>\n<title>/{0}</title>\n<h1>/{0}</h1>\n<ul>\n",
+            escape_html(&prefix)
+        );
+        if !prefix.is_empty() {
+            body.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+        for child in children {
+            body.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                percent_encode(&child),
+                escape_html(&child)
+            ));
+        }
+        body.push_str("</ul>\n");
+
+        write_head(
+            &mut stream,
+            "200 OK",
+            &[
+                ("Content-Type", "text/html; charset=utf-8".to_string()),
+                ("Content-Length", body.len().to_string()),
+            ],
+        )?;
+        if !head {
+            stream.write_all(body.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the request line and range header of a request from `reader`.
+///
+/// Returns `None` if the head is longer than [`MAX_HEAD_LENGTH`].
+fn read_head<R: Read>(reader: R) -> io::Result<Option<(String, Option<String>)>> {
+    let mut reader = BufReader::new(reader.take(MAX_HEAD_LENGTH));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            // Either the client stopped sending or the head is too long.
+            if reader.get_ref().limit() == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    Ok(Some((request_line, range)))
+}
+
+/// Write the data of `entry`, or the requested `range` of it, to the `stream`.
+fn serve_entry(
+    stream: &mut TcpStream,
+    path: &str,
+    entry: &Content,
+    range: Option<&str>,
+    head: bool,
+) -> io::Result<()> {
+    let length = entry.length()?;
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+    match range {
+        Some(range) => {
+            let range = match parse_range(range, length) {
+                Some(range) => range,
+                None => {
+                    return write_head(
+                        stream,
+                        "416 Range Not Satisfiable",
+                        &[
+                            ("Content-Range", format!("bytes */{length}")),
+                            ("Content-Length", "0".to_string()),
+                        ],
+                    )
+                }
+            };
+
+            write_head(
+                stream,
+                "206 Partial Content",
+                &[
+                    ("Content-Type", mime.to_string()),
+                    ("Content-Length", (range.end - range.start).to_string()),
+                    (
+                        "Content-Range",
+                        format!("bytes {}-{}/{length}", range.start, range.end - 1),
+                    ),
+                    ("Accept-Ranges", "bytes".to_string()),
+                ],
+            )?;
+            if !head {
+                copy_range(entry, stream, range)?;
+            }
+        }
+        None => {
+            write_head(
+                stream,
+                "200 OK",
+                &[
+                    ("Content-Type", mime.to_string()),
+                    ("Content-Length", length.to_string()),
+                    ("Accept-Ranges", "bytes".to_string()),
+                ],
+            )?;
+            if !head {
+                entry.copy_to(&mut Cursor::new(Vec::new()), stream)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the `range` of the data of `entry` to the `writer`.
+fn copy_range<W: Write>(entry: &Content, writer: &mut W, range: Range<u64>) -> io::Result<u64> {
+    if let Content::ArchiveRecord { archive, record } = entry {
+        let mut reader = BufReader::new(File::open(archive)?);
+        return record.copy_range(&mut reader, writer, range);
+    }
+
+    // Other content cannot seek, skip the data before the range instead.
+    let mut archive = Cursor::new(Vec::new());
+    let mut reader = entry.open(&mut archive)?;
+    io::copy(&mut reader.by_ref().take(range.start), &mut io::sink())?;
+    let copied = io::copy(&mut reader.take(range.end - range.start), writer)?;
+    Ok(copied)
+}
+
+/// Parse a single `bytes=` range header into a range clamped to `length`.
+///
+/// Returns `None` if the range is malformed or unsatisfiable.
+fn parse_range(value: &str, length: u64) -> Option<Range<u64>> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?.min(length);
+            length - suffix..length
+        }
+        (start, "") => start.parse::<u64>().ok()?..length,
+        (start, end) => {
+            start.parse::<u64>().ok()?..end.parse::<u64>().ok()?.saturating_add(1).min(length)
+        }
+    };
+
+    (range.start < range.end).then_some(range)
+}
+
+fn write_head(stream: &mut TcpStream, status: &str, headers: &[(&str, String)]) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn write_status(stream: &mut TcpStream, status: &str) -> io::Result<()> {
+    write_head(stream, status, &[("Content-Length", "0".to_string())])
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use super::*;
+
+    fn spawn_server(name: &str) -> (SocketAddr, PathBuf) {
+        let path = std::env::temp_dir().join(format!("warpa-{}-{name}.rpa", std::process::id()));
+
+        let mut archive = RenpyArchive::new();
+        archive
+            .content
+            .insert_raw("images/bg.txt", b"0123456789".to_vec());
+        archive
+            .content
+            .insert_raw("script.rpy", b"label start:".to_vec());
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+
+        let server = Server::bind("127.0.0.1:0", std::slice::from_ref(&path)).unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        (address, path)
+    }
+
+    fn request(address: SocketAddr, target: &str, headers: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "GET {target} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n"
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn should_list_directories() {
+        let (address, path) = spawn_server("list");
+
+        let response = request(address, "/", "");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("href=\"images/\""));
+        assert!(response.contains("href=\"script.rpy\""));
+
+        let response = request(address, "/images", "");
+        assert!(response.starts_with("HTTP/1.1 301"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_serve_entries_and_ranges() {
+        let (address, path) = spawn_server("range");
+
+        let response = request(address, "/images/bg.txt", "");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: text/plain"));
+        assert!(response.ends_with("\r\n\r\n0123456789"));
+
+        let response = request(address, "/images/bg.txt", "Range: bytes=2-5\r\n");
+        assert!(response.starts_with("HTTP/1.1 206 Partial Content"));
+        assert!(response.contains("Content-Range: bytes 2-5/10"));
+        assert!(response.ends_with("\r\n\r\n2345"));
+
+        let response = request(address, "/images/bg.txt", "Range: bytes=20-\r\n");
+        assert!(response.starts_with("HTTP/1.1 416"));
+
+        let response = request(address, "/missing.png", "");
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_copy_ranges_of_any_content() {
+        let path = std::env::temp_dir().join(format!("warpa-{}-range.txt", std::process::id()));
+        fs::write(&path, b"0123456789").unwrap();
+
+        for content in [
+            Content::Raw(b"0123456789".to_vec()),
+            Content::File(path.clone()),
+        ] {
+            let mut data = vec![];
+            copy_range(&content, &mut data, 2..6).unwrap();
+            assert_eq!(data, b"2345");
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_long_heads() {
+        let head = "GET / HTTP/1.1\r\nRange: bytes=1-2\r\n\r\n";
+        let (line, range) = read_head(head.as_bytes()).unwrap().unwrap();
+        assert_eq!(line, "GET / HTTP/1.1\r\n");
+        assert_eq!(range.as_deref(), Some("bytes=1-2"));
+
+        let head = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(10_000));
+        assert!(read_head(head.as_bytes()).unwrap().is_none());
+
+        let head = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000));
+        assert!(read_head(head.as_bytes()).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_parse_ranges() {
+        assert_eq!(parse_range("bytes=0-", 10), Some(0..10));
+        assert_eq!(parse_range("bytes=3-100", 10), Some(3..10));
+        assert_eq!(parse_range("bytes=-4", 10), Some(6..10));
+        assert_eq!(parse_range("bytes=10-", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+    }
+}
@@ -0,0 +1,60 @@
+use std::{fmt::Display, str::FromStr};
+
+/// A size in bytes parsed from a number with an optional binary suffix (K, M, G, T).
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_ascii_uppercase();
+        let number = upper.trim_end_matches('B').trim_end_matches('I');
+
+        let (digits, multiplier) = match number.chars().last() {
+            Some('K') => (&number[..number.len() - 1], 1 << 10),
+            Some('M') => (&number[..number.len() - 1], 1 << 20),
+            Some('G') => (&number[..number.len() - 1], 1 << 30),
+            Some('T') => (&number[..number.len() - 1], 1 << 40),
+            _ => (number, 1),
+        };
+
+        digits
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(multiplier))
+            .map(ByteSize)
+            .ok_or_else(|| format!("'{s}' not recognized as a size (e.g. 512, 64K, 4G)."))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_plain_bytes() {
+        assert_eq!("512".parse::<ByteSize>(), Ok(ByteSize(512)));
+    }
+
+    #[test]
+    fn should_parse_suffixed_sizes() {
+        assert_eq!("64K".parse::<ByteSize>(), Ok(ByteSize(64 << 10)));
+        assert_eq!("4g".parse::<ByteSize>(), Ok(ByteSize(4 << 30)));
+        assert_eq!("2MiB".parse::<ByteSize>(), Ok(ByteSize(2 << 20)));
+    }
+
+    #[test]
+    fn should_reject_invalid_sizes() {
+        assert!("".parse::<ByteSize>().is_err());
+        assert!("12X".parse::<ByteSize>().is_err());
+    }
+}
@@ -1,17 +1,33 @@
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use warpalib::ArchivePath;
 
 #[derive(Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct MappedPath {
-    key: PathBuf,
+    key: ArchivePath,
     value: Option<PathBuf>,
 }
 
-impl From<MappedPath> for (PathBuf, PathBuf) {
+impl MappedPath {
+    /// Whether the value should be read from stdin (`name=-`).
+    pub fn is_stdin(&self) -> bool {
+        self.value.as_deref() == Some(Path::new("-"))
+    }
+}
+
+impl From<MappedPath> for (ArchivePath, PathBuf) {
     fn from(mapped: MappedPath) -> Self {
         match mapped.value {
             Some(value) => (mapped.key, value),
-            None => (mapped.key.clone(), mapped.key),
+            None => {
+                let value = mapped.key.to_path();
+                (mapped.key, value)
+            }
         }
     }
 }
@@ -22,11 +38,11 @@ impl FromStr for MappedPath {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s.split_once('=') {
             Some((k, v)) => MappedPath {
-                key: PathBuf::from(k),
+                key: ArchivePath::from(k),
                 value: Some(PathBuf::from(v)),
             },
             None => MappedPath {
-                key: PathBuf::from(s),
+                key: ArchivePath::from(s),
                 value: None,
             },
         })
@@ -35,7 +51,7 @@ impl FromStr for MappedPath {
 
 impl Display for MappedPath {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.key.display())?;
+        write!(f, "{}", self.key)?;
         if let Some(value) = self.value.as_ref() {
             write!(f, "={}", value.display())?;
         }
@@ -51,7 +67,7 @@ mod tests {
     fn should_parse_entry_with_value_from_target() {
         let entry = "left/path=right/path".parse::<MappedPath>().unwrap();
         let expected = MappedPath {
-            key: PathBuf::from("left/path"),
+            key: ArchivePath::from("left/path"),
             value: Some(PathBuf::from("right/path")),
         };
 
@@ -62,10 +78,16 @@ mod tests {
     fn should_parse_entry_from_string() {
         let entry = "only/path".parse::<MappedPath>().unwrap();
         let expected = MappedPath {
-            key: PathBuf::from("only/path"),
+            key: ArchivePath::from("only/path"),
             value: None,
         };
 
         assert_eq!(entry, expected);
     }
+
+    #[test]
+    fn should_detect_stdin_value() {
+        assert!("name=-".parse::<MappedPath>().unwrap().is_stdin());
+        assert!(!"-".parse::<MappedPath>().unwrap().is_stdin());
+    }
 }
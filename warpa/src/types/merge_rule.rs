@@ -0,0 +1,47 @@
+use std::{fmt::Display, str::FromStr};
+
+use warpalib::MergePolicy;
+
+/// Defines which content is kept when archives share a path.
+#[derive(Clone, Default, Debug)]
+pub enum MergeRule {
+    #[default]
+    Last,
+    First,
+    Error,
+}
+
+impl FromStr for MergeRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(MergeRule::Last),
+            "first" => Ok(MergeRule::First),
+            "error" => Ok(MergeRule::Error),
+            _ => Err(format!(
+                "'{s}' not recognized as a merge rule (last, first, error)."
+            )),
+        }
+    }
+}
+
+impl Display for MergeRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeRule::Last => write!(f, "last"),
+            MergeRule::First => write!(f, "first"),
+            MergeRule::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl From<&MergeRule> for MergePolicy {
+    fn from(rule: &MergeRule) -> Self {
+        match rule {
+            MergeRule::Last => MergePolicy::LastWins,
+            MergeRule::First => MergePolicy::FirstWins,
+            MergeRule::Error => MergePolicy::Error,
+        }
+    }
+}
@@ -1,7 +1,13 @@
+mod byte_size;
 mod hex_key;
 mod mapped_path;
+mod merge_rule;
+mod overwrite_rule;
 mod write_version;
 
+pub use byte_size::ByteSize;
 pub use hex_key::HexKey;
 pub use mapped_path::MappedPath;
+pub use merge_rule::MergeRule;
+pub use overwrite_rule::OverwriteRule;
 pub use write_version::WriteVersion;
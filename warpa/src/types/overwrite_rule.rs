@@ -0,0 +1,55 @@
+use std::{fmt::Display, str::FromStr};
+
+use warpalib::OverwritePolicy;
+
+/// Defines what happens when an extracted file already exists.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum OverwriteRule {
+    #[default]
+    Overwrite,
+    Skip,
+    Error,
+    Different,
+    Rename,
+}
+
+impl FromStr for OverwriteRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(OverwriteRule::Overwrite),
+            "skip" => Ok(OverwriteRule::Skip),
+            "error" => Ok(OverwriteRule::Error),
+            "different" => Ok(OverwriteRule::Different),
+            "rename" => Ok(OverwriteRule::Rename),
+            _ => Err(format!(
+                "'{s}' not recognized as an overwrite rule (overwrite, skip, error, different, rename)."
+            )),
+        }
+    }
+}
+
+impl Display for OverwriteRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverwriteRule::Overwrite => write!(f, "overwrite"),
+            OverwriteRule::Skip => write!(f, "skip"),
+            OverwriteRule::Error => write!(f, "error"),
+            OverwriteRule::Different => write!(f, "different"),
+            OverwriteRule::Rename => write!(f, "rename"),
+        }
+    }
+}
+
+impl From<&OverwriteRule> for OverwritePolicy {
+    fn from(rule: &OverwriteRule) -> Self {
+        match rule {
+            OverwriteRule::Overwrite => OverwritePolicy::Overwrite,
+            OverwriteRule::Skip => OverwritePolicy::Skip,
+            OverwriteRule::Error => OverwritePolicy::Error,
+            OverwriteRule::Different => OverwritePolicy::Different,
+            OverwriteRule::Rename => OverwritePolicy::Rename,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use warpalib::RenpyArchive;
+
+/// A directory holding an archive with two files.
+fn archive_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("warpa-stdout-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut archive = RenpyArchive::new();
+    archive
+        .content
+        .insert_raw("script.rpy", b"label start:".to_vec());
+    archive.content.insert_raw("readme.txt", b"hello".to_vec());
+    archive
+        .flush(&mut File::create(dir.join("a.rpa")).unwrap())
+        .unwrap();
+
+    dir
+}
+
+/// Run warpa with the most verbose logging in `dir`.
+fn warpa(dir: &PathBuf, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_warpa"))
+        .current_dir(dir)
+        .arg("-vvvv")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+#[test]
+fn verbose_logs_should_not_corrupt_streamed_archives() {
+    let dir = archive_dir("merge");
+
+    let output = warpa(&dir, &["merge", "-", "a.rpa"]);
+    assert!(!output.stderr.is_empty());
+
+    let mut archive = RenpyArchive::read_buffered(&output.stdout[..]).unwrap();
+    let mut data = vec![];
+    archive.copy_file("readme.txt", &mut data).unwrap();
+    assert_eq!(data, b"hello");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verbose_logs_should_not_corrupt_printed_files() {
+    let dir = archive_dir("cat");
+
+    let output = warpa(&dir, &["cat", "a.rpa", "readme.txt"]);
+    assert_eq!(output.stdout, b"hello");
+
+    let output = warpa(&dir, &["index", "dump", "a.rpa"]);
+    let index = String::from_utf8(output.stdout).unwrap();
+    assert!(index.trim_start().starts_with('{'), "{index}");
+    assert!(index.trim_end().ends_with('}'), "{index}");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,68 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use warpalib::RenpyArchive;
+
+/// A directory holding `game/a.rpa` with `script.rpy` and `../secret.txt`, and both
+/// files on disk.
+fn game_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("warpa-update-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("game")).unwrap();
+    fs::write(dir.join("game/script.rpy"), b"label updated:").unwrap();
+    fs::write(dir.join("secret.txt"), b"secret").unwrap();
+
+    let mut archive = RenpyArchive::new();
+    archive
+        .content
+        .insert_raw("script.rpy", b"label start:".to_vec());
+    archive.content.insert_raw("../secret.txt", b"old".to_vec());
+    archive
+        .flush(&mut File::create(dir.join("game/a.rpa")).unwrap())
+        .unwrap();
+
+    dir
+}
+
+fn warpa(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_warpa"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn read(dir: &Path, path: &str) -> Vec<u8> {
+    let mut archive = RenpyArchive::open(&dir.join("game/a.rpa")).unwrap();
+    let mut data = vec![];
+    archive.copy_file(path, &mut data).unwrap();
+    data
+}
+
+#[test]
+fn should_refuse_to_update_unsafe_paths() {
+    let dir = game_dir("unsafe");
+
+    let output = warpa(&dir, &["update", "game/a.rpa"]);
+    assert!(!output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsafe paths"));
+    assert_eq!(read(&dir, "../secret.txt"), b"old");
+    assert_eq!(read(&dir, "script.rpy"), b"label start:");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn should_update_safe_paths_selected() {
+    let dir = game_dir("safe");
+
+    let output = warpa(&dir, &["update", "game/a.rpa", "script.rpy"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(read(&dir, "script.rpy"), b"label updated:");
+    assert_eq!(read(&dir, "../secret.txt"), b"old");
+
+    fs::remove_dir_all(dir).unwrap();
+}
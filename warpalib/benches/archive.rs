@@ -1,7 +1,51 @@
-use std::io::Cursor;
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use warpalib::RenpyArchive;
+use warpalib::{ParallelOptions, ReaderFn, RenpyArchive};
+
+/// Number of files in the archives written from storage.
+const FILES: usize = 2000;
+
+fn write_files() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("warpalib-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for i in 0..FILES {
+        fs::write(dir.join(format!("{i}.bin")), vec![i as u8; 4096]).unwrap();
+    }
+    dir
+}
+
+fn file_archive(dir: &Path) -> RenpyArchive<Cursor<Vec<u8>>> {
+    let mut archive = RenpyArchive::new();
+    for i in 0..FILES {
+        let name = format!("{i}.bin");
+        archive
+            .content
+            .insert_file_mapped(name.as_str(), dir.join(&name));
+    }
+    archive
+}
+
+/// An archive of sources taking a while to open, like files on network storage.
+fn slow_archive() -> RenpyArchive<Cursor<Vec<u8>>> {
+    let mut archive = RenpyArchive::new();
+    for i in 0..FILES / 10 {
+        let source = ReaderFn::new(move || {
+            thread::sleep(Duration::from_micros(500));
+            Ok(Cursor::new(vec![i as u8; 4096]))
+        });
+        archive
+            .content
+            .insert_source(format!("{i}.bin"), source.with_length(4096));
+    }
+    archive
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("archive write", |b| {
@@ -15,6 +59,45 @@ fn criterion_benchmark(c: &mut Criterion) {
             archive.flush(&mut buffer).unwrap();
         })
     });
+
+    let dir = write_files();
+    let mut group = c.benchmark_group("archive write files");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let mut buffer = Cursor::new(vec![]);
+            file_archive(&dir).flush(&mut buffer).unwrap();
+        })
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            let mut buffer = Cursor::new(vec![]);
+            file_archive(&dir)
+                .flush_parallel(&mut buffer, &ParallelOptions::default())
+                .unwrap();
+        })
+    });
+    group.bench_function("sequential slow", |b| {
+        b.iter(|| {
+            let mut buffer = Cursor::new(vec![]);
+            slow_archive().flush(&mut buffer).unwrap();
+        })
+    });
+    group.bench_function("parallel slow", |b| {
+        b.iter(|| {
+            let options = ParallelOptions {
+                threads: 16,
+                ..Default::default()
+            };
+            let mut buffer = Cursor::new(vec![]);
+            slow_archive()
+                .flush_parallel(&mut buffer, &options)
+                .unwrap();
+        })
+    });
+    group.finish();
+
+    fs::remove_dir_all(dir).unwrap();
 }
 
 criterion_group!(benches, criterion_benchmark);
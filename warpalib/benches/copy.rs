@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use warpalib::{Content, RenpyArchive};
+
+/// Size of the file stored in the benchmarked archive.
+const SIZE: usize = 256 << 20;
+
+/// Hides the file it wraps from [`io::copy`], forcing a copy through user space.
+struct Opaque<T>(T);
+
+impl<T: Read> Read for Opaque<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: Seek> Seek for Opaque<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl<T: Write> Write for Opaque<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn build_archive(dir: &Path) -> PathBuf {
+    let path = dir.join("video.rpa");
+    let mut archive = RenpyArchive::new();
+    archive.content.insert_raw("video.webm", vec![7u8; SIZE]);
+    archive.flush(&mut File::create(&path).unwrap()).unwrap();
+    path
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("warpalib-copy-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = build_archive(&dir);
+    let out = dir.join("out.bin");
+
+    let archive = RenpyArchive::open(&path).unwrap();
+    let record = match archive.content.get("video.webm") {
+        Some(Content::Record(record)) => record.clone(),
+        _ => unreachable!(),
+    };
+    let mut reader = archive.reader;
+
+    let mut group = c.benchmark_group("record extract");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    group.bench_function("kernel", |b| {
+        b.iter(|| {
+            let mut file = File::create(&out).unwrap();
+            record.copy_section(&mut reader, &mut file).unwrap();
+        })
+    });
+    group.bench_function("user space", |b| {
+        b.iter(|| {
+            let mut file = Opaque(File::create(&out).unwrap());
+            record
+                .copy_section(&mut Opaque(&mut reader), &mut file)
+                .unwrap();
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("archive flush");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    group.bench_function("kernel", |b| {
+        b.iter(|| {
+            let archive = RenpyArchive::open(&path).unwrap();
+            archive.flush(&mut File::create(&out).unwrap()).unwrap();
+        })
+    });
+    group.bench_function("user space", |b| {
+        b.iter(|| {
+            let archive = RenpyArchive::open(&path).unwrap();
+            let archive = RenpyArchive {
+                reader: BufReader::new(Opaque(archive.reader.into_inner())),
+                key: archive.key,
+                offset: archive.offset,
+                version: archive.version,
+                content: archive.content,
+            };
+            archive
+                .flush(&mut Opaque(File::create(&out).unwrap()))
+                .unwrap();
+        })
+    });
+    group.finish();
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
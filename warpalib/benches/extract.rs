@@ -0,0 +1,133 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::Path,
+    thread,
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize::PerIteration, Criterion, Throughput};
+use warpalib::{
+    ArchivePath, CancelToken, Content, ExtractOptions, Hooks, RenpyArchive, SharedArchive,
+};
+
+/// Number of entries in the benchmarked archive.
+const ENTRIES: usize = 4096;
+
+/// Size of each entry in the benchmarked archive.
+const ENTRY_SIZE: usize = 64 << 10;
+
+/// Number of threads reading in parallel.
+const THREADS: usize = 4;
+
+fn build_archive(path: &Path) {
+    let mut archive = RenpyArchive::new();
+    for i in 0..ENTRIES {
+        archive
+            .content
+            .insert_raw(format!("movies/{i}.webm"), vec![i as u8; ENTRY_SIZE]);
+    }
+    archive.flush(&mut File::create(path).unwrap()).unwrap();
+}
+
+/// Evict the archive from the page cache before each iteration when
+/// `WARPALIB_BENCH_COLD` is set, which needs root on Linux.
+fn drop_caches() {
+    if std::env::var_os("WARPALIB_BENCH_COLD").is_some() {
+        fs::write("/proc/sys/vm/drop_caches", "1").expect("Failed to drop caches");
+    }
+}
+
+fn read_all(archive: &SharedArchive, entries: &[(&ArchivePath, &Content)]) {
+    let mut reader = BufReader::with_capacity(1 << 16, archive.reader());
+    for (_, content) in entries {
+        content.copy_to(&mut reader, &mut io::sink()).unwrap();
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("warpalib-extract-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("movies.rpa");
+    build_archive(&path);
+
+    let archive = SharedArchive::open(&path).unwrap();
+    let hashed = archive.content.iter().collect::<Vec<_>>();
+    let ordered = archive.content.sorted_by_offset();
+
+    let mut group = c.benchmark_group("extract");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((ENTRIES * ENTRY_SIZE) as u64));
+    group.bench_function("hash order", |b| {
+        b.iter_batched(drop_caches, |_| read_all(&archive, &hashed), PerIteration)
+    });
+    group.bench_function("offset order", |b| {
+        b.iter_batched(drop_caches, |_| read_all(&archive, &ordered), PerIteration)
+    });
+
+    // Threads taking every n-th entry jump around the archive, even in offset order.
+    group.bench_function("parallel interleaved", |b| {
+        b.iter_batched(
+            drop_caches,
+            |_| {
+                thread::scope(|scope| {
+                    for t in 0..THREADS {
+                        let entries = hashed
+                            .iter()
+                            .skip(t)
+                            .step_by(THREADS)
+                            .copied()
+                            .collect::<Vec<_>>();
+                        let archive = &archive;
+                        scope.spawn(move || read_all(archive, &entries));
+                    }
+                })
+            },
+            PerIteration,
+        )
+    });
+    group.bench_function("parallel offset chunks", |b| {
+        b.iter_batched(
+            drop_caches,
+            |_| {
+                thread::scope(|scope| {
+                    for chunk in ordered.chunks(ordered.len().div_ceil(THREADS)) {
+                        let archive = &archive;
+                        scope.spawn(move || read_all(archive, chunk));
+                    }
+                })
+            },
+            PerIteration,
+        )
+    });
+    group.finish();
+
+    // Extract to disk with the options of `warpa extract`, which cancels on Ctrl-C.
+    let out = dir.join("out");
+    let options = ExtractOptions::new().with_hooks(Hooks::new().with_cancel(CancelToken::new()));
+    let mut group = c.benchmark_group("extract to disk");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((ENTRIES * ENTRY_SIZE) as u64));
+    group.bench_function("shared archive", |b| {
+        b.iter_batched(
+            drop_caches,
+            |_| assert!(archive.extract_to(&out, &options).is_success()),
+            PerIteration,
+        )
+    });
+    group.bench_function("buffered archive", |b| {
+        b.iter_batched(
+            || {
+                drop_caches();
+                RenpyArchive::open(&path).unwrap()
+            },
+            |mut archive| assert!(archive.extract_to(&out, &options).is_success()),
+            PerIteration,
+        )
+    });
+    group.finish();
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use flate2::read::ZlibDecoder;
+use serde_pickle::{DeOptions, Value};
+use warpalib::{Record, RenpyArchive};
+
+/// Number of entries in the benchmarked archive.
+const ENTRIES: usize = 100_000;
+
+/// Allocator tracking the peak number of bytes allocated.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Bytes allocated at the peak of `f`, on top of what was allocated before.
+fn peak_memory<T, F: FnOnce() -> T>(f: F) -> usize {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    drop(f());
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+fn build_archive() -> Vec<u8> {
+    let mut archive = RenpyArchive::new();
+    for i in 0..ENTRIES {
+        archive
+            .content
+            .insert_raw(format!("images/sprites/character_{i}.png"), vec![0u8; 4]);
+    }
+
+    let mut buffer = Cursor::new(vec![]);
+    archive.flush(&mut buffer).unwrap();
+    buffer.into_inner()
+}
+
+/// Open the index by buffering the whole tail and inflating it before unpickling
+/// into values, the way indexes used to be read.
+fn open_buffered(data: &[u8]) -> io::Result<HashMap<String, Record>> {
+    let mut reader = Cursor::new(data);
+    let mut header = String::new();
+    reader.by_ref().take(34).read_to_string(&mut header)?;
+    let mut parts = header.trim_end().split(' ').skip(1);
+    let offset = u64::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    let key = u64::from_str_radix(parts.next().unwrap(), 16).unwrap();
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut compressed = Vec::new();
+    reader.read_to_end(&mut compressed)?;
+
+    let mut contents = Vec::new();
+    io::copy(
+        &mut ZlibDecoder::new(Cursor::new(compressed)),
+        &mut contents,
+    )?;
+
+    let values: HashMap<String, Value> =
+        serde_pickle::from_slice(&contents, DeOptions::default()).unwrap();
+    Ok(values
+        .into_iter()
+        .map(|(k, v)| (k, Record::from_value(v, Some(key)).unwrap()))
+        .collect())
+}
+
+/// Inflate the index while unpickling it into values with `serde_pickle`.
+fn open_pickle_streaming(data: &[u8]) -> io::Result<HashMap<String, Record>> {
+    let mut reader = Cursor::new(data);
+    let mut header = String::new();
+    reader.by_ref().take(34).read_to_string(&mut header)?;
+    let mut parts = header.trim_end().split(' ').skip(1);
+    let offset = u64::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    let key = u64::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let decoder = flate2::bufread::ZlibDecoder::new(reader);
+    let values: HashMap<String, Value> =
+        serde_pickle::from_reader(decoder, DeOptions::default()).unwrap();
+    Ok(values
+        .into_iter()
+        .map(|(k, v)| (k, Record::from_value(v, Some(key)).unwrap()))
+        .collect())
+}
+
+fn open_streaming(data: &[u8]) -> RenpyArchive<Cursor<&[u8]>> {
+    RenpyArchive::read(Cursor::new(data)).unwrap()
+}
+
+/// Open the index and build its records by accessing the content.
+fn open_accessed(data: &[u8]) -> RenpyArchive<Cursor<&[u8]>> {
+    let archive = open_streaming(data);
+    assert_eq!(archive.content.len(), ENTRIES);
+    archive
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let data = build_archive();
+
+    println!(
+        "peak memory opening {ENTRIES} entries: buffered {} KiB, pickle streaming {} KiB, streaming {} KiB, accessed {} KiB",
+        peak_memory(|| open_buffered(&data)) >> 10,
+        peak_memory(|| open_pickle_streaming(&data)) >> 10,
+        peak_memory(|| open_streaming(&data)) >> 10,
+        peak_memory(|| open_accessed(&data)) >> 10,
+    );
+
+    let mut group = c.benchmark_group("index open");
+    group.sample_size(10);
+    group.bench_function("buffered", |b| b.iter(|| open_buffered(&data)));
+    group.bench_function("pickle streaming", |b| {
+        b.iter(|| open_pickle_streaming(&data))
+    });
+    group.bench_function("streaming", |b| b.iter(|| open_streaming(&data)));
+    group.bench_function("accessed", |b| b.iter(|| open_accessed(&data)));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
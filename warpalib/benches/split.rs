@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, BatchSize::LargeInput, Criterion};
+use warpalib::{ContentMap, RpaVersion};
+
+/// Small entries, where the index dominates the size of each volume.
+fn build_map(entries: usize) -> ContentMap {
+    let mut map = ContentMap::default();
+    for i in 0..entries {
+        map.insert_raw(format!("images/sprites/{i:06}.png"), vec![0u8; 16]);
+    }
+    map
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split");
+    group.sample_size(10);
+
+    for (entries, max_size) in [
+        (2_000, 128 << 10),
+        (20_000, 256 << 10),
+        (100_000, 256 << 10),
+    ] {
+        let name = format!("{entries} entries into {} KiB volumes", max_size >> 10);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || build_map(entries),
+                |map| {
+                    map.split_by_size(max_size, &RpaVersion::V3_0, Some(0xDEADBEEF))
+                        .unwrap()
+                },
+                LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
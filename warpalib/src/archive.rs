@@ -2,14 +2,19 @@ use std::{
     collections::{BTreeMap, HashMap},
     fs::File,
     io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
-use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
-use log::{debug, trace};
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
+use log::{debug, trace, warn};
 use serde_pickle::{DeOptions, HashableValue, SerOptions, Value};
 
-use crate::{record::Record, version::RpaVersion, Content, ContentMap, RpaError, RpaResult};
+use crate::{
+    index::{decode_index, IndexError},
+    record::Record,
+    version::RpaVersion,
+    ArchivePath, Content, ContentMap, Entry, Hooks, RpaError, RpaResult,
+};
 
 /// Represents a renpy archive.
 ///
@@ -101,6 +106,39 @@ impl RenpyArchive<BufReader<File>> {
 
 type MetaData = (u64, Option<u64>, ContentMap);
 
+impl RenpyArchive<Cursor<Vec<u8>>> {
+    /// Open an archive from a `reader` that cannot seek, such as stdin or a pipe.
+    ///
+    /// The index is stored at the end of an archive, so the whole archive is
+    /// buffered into memory before it is parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::RenpyArchive;
+    ///
+    /// let mut archive = RenpyArchive::new();
+    /// archive.content.insert_raw("log.txt", b"hello".to_vec());
+    ///
+    /// let mut buffer = vec![];
+    /// archive.flush_stream(&mut buffer).expect("Failed to write archive");
+    ///
+    /// let mut archive = RenpyArchive::read_buffered(&buffer[..]).expect("Failed to read archive");
+    /// let mut data = vec![];
+    /// archive.copy_file("log.txt", &mut data).unwrap();
+    /// assert_eq!(data, b"hello");
+    /// ```
+    pub fn read_buffered<R: Read>(mut reader: R) -> RpaResult<Self> {
+        trace!("Buffering archive from reader");
+
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        debug!("Buffered archive ({} bytes)", buffer.len());
+
+        RenpyArchive::read(Cursor::new(buffer))
+    }
+}
+
 impl<R> RenpyArchive<R>
 where
     R: Seek + BufRead,
@@ -130,6 +168,38 @@ where
 
     /// Retrieve `offset`, `key`, and content indexes from the archive
     pub fn metadata(reader: &mut R, version: &RpaVersion) -> RpaResult<MetaData> {
+        let (offset, key) = Self::header(reader, version)?;
+
+        trace!("Commencing index retrieval");
+        reader.seek(SeekFrom::Start(offset))?;
+        let content = match decode_index(&mut *reader, key) {
+            Ok(content) => content,
+            Err(IndexError::Rpa(e)) => return Err(e),
+            Err(IndexError::Unsupported(op)) => {
+                debug!("Index uses unsupported pickle opcode ({op:#04x}), decoding values");
+
+                let mut content = HashMap::new();
+                for (path, value) in Self::read_index(reader, offset)? {
+                    let value = Record::from_value(value, key)?;
+                    let path = ArchivePath::from(path);
+                    if content
+                        .insert(path.clone(), Content::Record(value))
+                        .is_some()
+                    {
+                        warn!("Index has multiple paths normalized to ({path}), only one is kept");
+                    }
+                }
+                content.into()
+            }
+        };
+        debug!("Parsed index data to struct");
+
+        Ok((offset, key, content))
+    }
+
+    /// Retrieve the index `offset` and `key` from the rest of the header line,
+    /// after the version has been read.
+    pub fn header(reader: &mut R, version: &RpaVersion) -> RpaResult<(u64, Option<u64>)> {
         trace!("Parsing metadata from archive version ({version})");
 
         let mut first_line = String::new();
@@ -162,35 +232,30 @@ where
         };
         debug!("Parsed the obfuscation key: {key:?}");
 
-        trace!("Commencing index retrieval");
+        Ok((offset, key))
+    }
 
-        // Retrieve indexes.
+    /// Read the raw index stored at `offset` as pickle values.
+    ///
+    /// Values are left obfuscated. Use [`Record::from_value`] with the archive key
+    /// to convert them into records.
+    pub fn read_index(reader: &mut R, offset: u64) -> RpaResult<HashMap<String, Value>> {
+        // Inflate and deserialize indexes while reading them.
         reader.seek(SeekFrom::Start(offset))?;
-        let mut contents = Vec::new();
-        reader.read_to_end(&mut contents)?;
-        debug!("Read raw index bytes");
-
-        // Decode indexes data.
-        let mut decoder = ZlibDecoder::new(Cursor::new(contents));
-        let mut contents = Vec::new();
-        io::copy(&mut decoder, &mut contents)?;
-        debug!("Decoded index data with zlib");
-
-        // Deserialize indexes using pickle.
-        let options = DeOptions::default();
-        let raw_indexes: HashMap<String, Value> = serde_pickle::from_slice(&contents[..], options)
-            .map_err(|_| RpaError::DeserializeRecord)?;
+        let decoder = ZlibDecoder::new(reader);
+        // Python 3 pickles empty bytes by calling `bytes`, which serde_pickle does not
+        // resolve, and loses the memoized result with protocol 0. Both are read as `None`.
+        let options = DeOptions::default()
+            .replace_unresolved_globals()
+            .replace_recursive_structures();
+        let raw_indexes: HashMap<String, Value> = serde_pickle::from_reader(decoder, options)
+            .map_err(|e| match e {
+                serde_pickle::Error::Io(e) => RpaError::Io(e),
+                _ => RpaError::DeserializeRecord,
+            })?;
         debug!("Deserialized index data using pickle");
 
-        // Map indexes to an easier format.
-        let mut content = HashMap::new();
-        for (path, value) in raw_indexes.into_iter() {
-            let value = Record::from_value(value, key)?;
-            content.insert(PathBuf::from(path), Content::Record(value));
-        }
-        debug!("Parsed index data to struct");
-
-        Ok((offset, key, content.into()))
+        Ok(raw_indexes)
     }
 }
 
@@ -204,14 +269,57 @@ where
     ///
     /// This function returns `NotFound` error if `path` is not present in
     /// the archive and any errors raised during the copy process.
-    pub fn copy_file<W: Write>(&mut self, path: &Path, writer: &mut W) -> RpaResult<u64> {
-        if let Some(content) = self.content.get(Path::new(path)) {
-            return content
-                .copy_to(&mut self.reader, writer)
-                .map_err(|e| e.into());
+    pub fn copy_file<P, W>(&mut self, path: P, writer: &mut W) -> RpaResult<u64>
+    where
+        P: Into<ArchivePath>,
+        W: Write,
+    {
+        self.copy_file_with(path, writer, &Hooks::default())
+    }
+
+    /// Copy content from a file in the archive to the `writer`, reporting progress
+    /// to and checking cancellation of the `hooks`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the errors of [`RenpyArchive::copy_file`] and
+    /// `Cancelled` if cancelled, leaving the content partially copied.
+    pub fn copy_file_with<P, W>(&mut self, path: P, writer: &mut W, hooks: &Hooks) -> RpaResult<u64>
+    where
+        P: Into<ArchivePath>,
+        W: Write,
+    {
+        let path = path.into();
+        if let Some(content) = self.content.get(&path) {
+            hooks.started([content].into_iter());
+            return hooks.copy(&path, content, &mut self.reader, writer);
         }
 
-        Err(RpaError::NotFound(path.to_path_buf()))
+        Err(RpaError::NotFound(path))
+    }
+
+    /// Return a view of every file in the archive, in no particular order.
+    ///
+    /// Use [`ContentMap::entries`] on `content` instead to open readers of the
+    /// entries from `reader` while iterating.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{EntryKind, RenpyArchive};
+    ///
+    /// let mut archive = RenpyArchive::new();
+    /// archive.content.insert_raw("a.txt", b"hello".to_vec());
+    ///
+    /// for entry in archive.entries() {
+    ///     assert_eq!(entry.path().as_str(), "a.txt");
+    ///     assert_eq!(entry.kind(), EntryKind::Raw);
+    ///     assert_eq!(entry.size().unwrap(), 5);
+    ///     assert_eq!(entry.offset(), None);
+    /// }
+    /// ```
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> {
+        self.content.entries()
     }
 }
 
@@ -232,7 +340,25 @@ where
     /// # Warnings
     ///
     /// Take care not to write to the same archive as being read from.
-    pub fn flush<W: Seek + Write>(mut self, writer: &mut W) -> RpaResult<()> {
+    pub fn flush<W: Seek + Write>(self, writer: &mut W) -> RpaResult<()> {
+        self.flush_with(writer, &Hooks::default())?;
+        Ok(())
+    }
+
+    /// Consume and write the archive to the `writer` like [`RenpyArchive::flush`],
+    /// reporting progress to and checking cancellation of the `hooks`.
+    ///
+    /// Returns the index as written: a deobfuscated record of each file, relative to
+    /// where the writer was positioned.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Cancelled` if cancelled, leaving the output incomplete.
+    pub fn flush_with<W: Seek + Write>(
+        mut self,
+        writer: &mut W,
+        hooks: &Hooks,
+    ) -> RpaResult<ContentMap> {
         trace!("Commencing archive flush");
 
         let mut offset: u64 = 0;
@@ -249,77 +375,278 @@ where
 
         // Build indexes while writing to the archive.
         trace!("Rebuilding indexes from content");
-        let mut indexes = HashMap::new();
+        let mut indexes = Vec::with_capacity(self.content.len());
 
-        // Copy data from content.
-        for (path, content) in self.content.into_iter() {
-            let length = content.copy_to(&mut self.reader, writer)?;
-            let path = path.as_os_str().to_string_lossy().to_string();
+        // Copy data from content, reading records sequentially.
+        let entries = self.content.into_sorted_by_offset();
+        hooks.started(entries.iter().map(|(_, content)| content));
+        for (path, content) in entries {
+            let length = hooks.copy(&path, &content, &mut self.reader, writer)?;
             debug!("Written content from path ({path}) length ({length} bytes)",);
 
-            indexes.insert(path, Record::new(offset, length, None, self.key));
+            indexes.push((path, Record::new(offset, length, None, None)));
             offset += length;
         }
 
-        {
-            trace!("Preparing to write indexes");
-
-            // Convert indexes into serializable values.
-            let values = Value::Dict(BTreeMap::from_iter(
-                indexes
-                    .into_iter()
-                    .map(|(k, v)| (HashableValue::String(k), v.into_value())),
-            ));
-
-            // Serialize indexes with picke protocol 2.
-            let mut buffer = Vec::new();
-            let options = SerOptions::new().proto_v2();
-            match serde_pickle::value_to_writer(&mut buffer, &values, options) {
-                Ok(_) => Ok(()),
-                Err(serde_pickle::Error::Io(e)) => Err(RpaError::Io(e)),
-                Err(_) => Err(RpaError::SerializeRecord),
-            }?;
-            debug!(
-                "Encoded indexes using pickle format 2: {} bytes",
-                buffer.len()
-            );
-
-            // Compress serialized data with zlib.
-            let mut input = Cursor::new(buffer);
-            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-            io::copy(&mut input, &mut encoder)?;
-            let compressed = encoder.finish()?;
-            debug!("Compressed indexes using zlib: {} bytes", compressed.len());
-
-            // Write compressed data to writer.
-            let mut cursor = Cursor::new(compressed);
-            io::copy(&mut cursor, writer)?;
-            debug!("Done writing indexes");
+        let written = write_written_index(writer, indexes, self.key)?;
+        write_header(writer, &self.version, offset, self.key)?;
+
+        // And done.
+        writer.flush()?;
+        debug!("Done writing archive");
+
+        Ok(written)
+    }
+
+    /// Consume and write the archive to the `writer`, returning an archive reading
+    /// from the reader `reopen` turns the flushed writer into.
+    ///
+    /// The returned archive uses the index as written instead of parsing it again,
+    /// so it can be modified and written again right away. The `writer` is expected to
+    /// be positioned at its start. To replace an archive file, use
+    /// [`RenpyArchive::save_atomic`] instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::{fs::File, io::{BufReader, Cursor}};
+    /// use warpalib::RenpyArchive;
+    ///
+    /// let mut archive = RenpyArchive::new();
+    /// archive.content.insert_raw("a.txt", b"hello".to_vec());
+    ///
+    /// // A cursor can be read back as is.
+    /// let mut archive = archive.flush_into(Cursor::new(vec![]), Ok).unwrap();
+    /// archive.content.insert_raw("b.txt", b"world".to_vec());
+    ///
+    /// // A file created for writing is opened again for reading.
+    /// let path = std::env::temp_dir().join("warpalib-flush-into-example.rpa");
+    /// let mut archive = archive
+    ///     .flush_into(File::create(&path).unwrap(), |_| {
+    ///         File::open(&path).map(BufReader::new)
+    ///     })
+    ///     .unwrap();
+    ///
+    /// let mut data = vec![];
+    /// archive.copy_file("a.txt", &mut data).unwrap();
+    /// assert_eq!(data, b"hello");
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn flush_into<W, T, F>(self, mut writer: W, reopen: F) -> RpaResult<RenpyArchive<T>>
+    where
+        W: Seek + Write,
+        T: Seek + BufRead,
+        F: FnOnce(W) -> io::Result<T>,
+    {
+        let (key, version) = (self.key, self.version.clone());
+        let content = self.flush_with(&mut writer, &Hooks::default())?;
+        RenpyArchive::from_written(reopen(writer)?, key, version, content)
+    }
+
+    /// Create an archive reading from the output of a flush that wrote `content`.
+    pub(crate) fn from_written(
+        reader: R,
+        key: Option<u64>,
+        version: RpaVersion,
+        content: ContentMap,
+    ) -> RpaResult<Self> {
+        // The index is written right after the data.
+        let offset = content
+            .values()
+            .filter_map(|content| match content {
+                Content::Record(record) => Some(record.start + record.length),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(version.header_length()? as u64);
+
+        Ok(Self {
+            reader,
+            key,
+            offset,
+            version,
+            content,
+        })
+    }
+
+    /// Consume and write the archive to a `writer` that cannot seek, such as stdout
+    /// or a pipe.
+    ///
+    /// Unlike [`RenpyArchive::flush`], the header is written first. The length of
+    /// every content is resolved beforehand to work out where the index is stored,
+    /// which reads the metadata of `File` content.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error for `Source` content without a known length,
+    /// and `LengthMismatch` if content does not match its resolved length when copied.
+    /// The output is incomplete in both cases.
+    pub fn flush_stream<W: Write>(self, writer: &mut W) -> RpaResult<()> {
+        self.flush_stream_with(writer, &Hooks::default())?;
+        Ok(())
+    }
+
+    /// Consume and write the archive to a `writer` that cannot seek like
+    /// [`RenpyArchive::flush_stream`], reporting progress to and checking cancellation
+    /// of the `hooks`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the errors of [`RenpyArchive::flush_stream`] and
+    /// `Cancelled` if cancelled, leaving the output incomplete.
+    pub fn flush_stream_with<W: Write>(
+        mut self,
+        writer: &mut W,
+        hooks: &Hooks,
+    ) -> RpaResult<ContentMap> {
+        trace!("Commencing streaming archive flush");
+
+        // Lay out the data section to find the index offset.
+        let mut offset = self.version.header_length()? as u64;
+        let mut entries = Vec::with_capacity(self.content.len());
+        for (path, content) in self.content.into_sorted_by_offset() {
+            let length = content.length()?;
+            entries.push((path, content, Record::new(offset, length, None, None)));
+            offset += length;
         }
+        debug!(
+            "Resolved index offset ({offset}) of {} entries",
+            entries.len()
+        );
 
-        // Back to start, time to write the header.
-        trace!("Rewinding and writing archive header");
-        writer.rewind()?;
+        hooks.started(entries.iter().map(|(_, content, _)| content));
+        writer.write_all(&header_bytes(&self.version, offset, self.key)?)?;
 
-        let key = self.key.unwrap_or(0);
-        let header = match self.version {
-            RpaVersion::V3_0 => format!("RPA-3.0 {:016x} {:08x}\n", offset, key),
-            RpaVersion::V2_0 => format!("RPA-2.0 {:016x}\n", offset),
-            v @ (RpaVersion::V3_2 | RpaVersion::V1_0) => {
-                return Err(RpaError::WritingNotSupported(v))
+        // Copy data from content.
+        let mut indexes = Vec::with_capacity(entries.len());
+        for (path, content, record) in entries {
+            let length = hooks.copy(&path, &content, &mut self.reader, writer)?;
+            if length != record.length {
+                return Err(RpaError::LengthMismatch(path, record.length, length));
             }
-        };
+            debug!("Written content from path ({path}) length ({length} bytes)");
 
-        {
-            let header = header.into_bytes();
-            writer.write_all(&header)?;
-            debug!("Written header ({} bytes) key ({})", header.len(), key);
+            indexes.push((path, record));
         }
 
-        // And done.
+        let written = write_written_index(writer, indexes, self.key)?;
+
         writer.flush()?;
         debug!("Done writing archive");
 
-        Ok(())
+        Ok(written)
     }
 }
+
+/// Write the `indexes` of a flush and return them as the content of the written archive.
+pub(crate) fn write_written_index<W: Write>(
+    writer: &mut W,
+    indexes: Vec<(ArchivePath, Record)>,
+    key: Option<u64>,
+) -> RpaResult<ContentMap> {
+    let serialized = indexes
+        .iter()
+        .map(|(path, record)| (path.to_string(), record.clone()));
+    write_index(writer, serialized, key)?;
+
+    let written = indexes
+        .into_iter()
+        .map(|(path, record)| (path, Content::Record(record)))
+        .collect::<HashMap<_, _>>();
+    Ok(written.into())
+}
+
+/// Serialize `indexes` with pickle, compress them with zlib and write them to the
+/// `writer`, returning the number of bytes written.
+///
+/// Records are expected to be deobfuscated and are obfuscated with `key` if given.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use warpalib::{write_index, Record, RenpyArchive};
+///
+/// let mut buffer = Cursor::new(vec![]);
+/// let indexes = [("a.txt".to_string(), Record::new(34, 5, None, None))];
+/// write_index(&mut buffer, indexes, Some(0xDEADBEEF)).unwrap();
+///
+/// let raw = RenpyArchive::read_index(&mut buffer, 0).unwrap();
+/// let record = Record::from_value(raw["a.txt"].clone(), Some(0xDEADBEEF)).unwrap();
+/// assert_eq!(record, Record::new(34, 5, None, None));
+/// ```
+pub fn write_index<W, I>(writer: &mut W, indexes: I, key: Option<u64>) -> RpaResult<u64>
+where
+    W: Write,
+    I: IntoIterator<Item = (String, Record)>,
+{
+    trace!("Preparing to write indexes");
+
+    // Convert indexes into serializable values.
+    let values = Value::Dict(BTreeMap::from_iter(indexes.into_iter().map(|(k, v)| {
+        let obfuscated = Record::new(v.start, v.length, v.prefix, key);
+        (HashableValue::String(k), obfuscated.into_value())
+    })));
+
+    // Serialize indexes with picke protocol 2.
+    let mut buffer = Vec::new();
+    let options = SerOptions::new().proto_v2();
+    match serde_pickle::value_to_writer(&mut buffer, &values, options) {
+        Ok(_) => Ok(()),
+        Err(serde_pickle::Error::Io(e)) => Err(RpaError::Io(e)),
+        Err(_) => Err(RpaError::SerializeRecord),
+    }?;
+    debug!(
+        "Encoded indexes using pickle format 2: {} bytes",
+        buffer.len()
+    );
+
+    // Compress serialized data with zlib.
+    let mut input = Cursor::new(buffer);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    let compressed = encoder.finish()?;
+    debug!("Compressed indexes using zlib: {} bytes", compressed.len());
+
+    // Write compressed data to writer.
+    writer.write_all(&compressed)?;
+    debug!("Done writing indexes");
+
+    Ok(compressed.len() as u64)
+}
+
+/// Rewind the `writer` and write the archive header pointing to the index at `offset`.
+///
+/// # Errors
+///
+/// This function returns `WritingNotSupported` for v3.2 and v1.0.
+pub fn write_header<W: Write + Seek>(
+    writer: &mut W,
+    version: &RpaVersion,
+    offset: u64,
+    key: Option<u64>,
+) -> RpaResult<()> {
+    // Back to start, time to write the header.
+    trace!("Rewinding and writing archive header");
+    writer.rewind()?;
+
+    let header = header_bytes(version, offset, key)?;
+    writer.write_all(&header)?;
+    debug!("Written header ({} bytes) key ({key:?})", header.len());
+
+    Ok(())
+}
+
+/// Format the header of an archive with the index at `offset`.
+fn header_bytes(version: &RpaVersion, offset: u64, key: Option<u64>) -> RpaResult<Vec<u8>> {
+    let key = key.unwrap_or(0);
+    let header = match version {
+        RpaVersion::V3_0 => format!("RPA-3.0 {:016x} {:08x}\n", offset, key),
+        RpaVersion::V2_0 => format!("RPA-2.0 {:016x}\n", offset),
+        v @ (RpaVersion::V3_2 | RpaVersion::V1_0) => {
+            return Err(RpaError::WritingNotSupported(v.clone()))
+        }
+    };
+
+    Ok(header.into_bytes())
+}
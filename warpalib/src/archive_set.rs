@@ -0,0 +1,269 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+
+use crate::{ArchivePath, ContentMap, RenpyArchive, RpaResult};
+
+/// The extension of archives loaded from a game directory.
+const ARCHIVE_EXTENSION: &str = "rpa";
+
+/// Represents where the data of a path is provided from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Provider {
+    /// A loose file in the game directory.
+    File(PathBuf),
+
+    /// An archive in the game directory.
+    Archive(PathBuf),
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provider::File(path) => write!(f, "{} (file)", path.display()),
+            Provider::Archive(path) => write!(f, "{} (archive)", path.display()),
+        }
+    }
+}
+
+/// Represents all archives of a game directory resolved in the order renpy loads them.
+///
+/// Renpy looks for loose files in the game directory first. Then archives are checked
+/// in reverse order of their file names, so `patch.rpa` shadows `images.rpa`. Like renpy,
+/// paths in archives are matched ignoring case.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::path::Path;
+/// use warpalib::{ArchivePath, ArchiveSet};
+///
+/// let set = ArchiveSet::open(Path::new("game")).unwrap();
+///
+/// // Find which archive or file provides the sprite.
+/// if let Some(provider) = set.resolve(&ArchivePath::from("images/eileen.png")) {
+///     println!("{provider}");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ArchiveSet {
+    /// The game directory.
+    pub root: PathBuf,
+
+    /// Archives with their detached content in the order they are checked.
+    pub archives: Vec<(PathBuf, ContentMap)>,
+}
+
+impl ArchiveSet {
+    /// Open every archive in the game directory `root`.
+    ///
+    /// Archives in subdirectories are not loaded.
+    pub fn open(root: &Path) -> RpaResult<Self> {
+        trace!("Opening archives in directory: {}", root.display());
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(root)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e == ARCHIVE_EXTENSION) {
+                paths.push(path);
+            }
+        }
+
+        // Renpy checks archives in reverse order of their names.
+        paths.sort_by(|a, b| b.file_name().cmp(&a.file_name()));
+
+        let mut archives = Vec::with_capacity(paths.len());
+        for path in paths {
+            debug!("Loading archive: {}", path.display());
+            let archive = RenpyArchive::open(&path)?;
+            let content = archive.content.into_external(&path);
+            archives.push((path, content));
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            archives,
+        })
+    }
+
+    /// Every provider of `path` in the order renpy checks them.
+    ///
+    /// The first provider is the one used by renpy, the rest are shadowed.
+    pub fn providers(&self, path: &ArchivePath) -> Vec<Provider> {
+        let mut providers = Vec::new();
+
+        let file = self.root.join(path.to_path());
+        if file.is_file() {
+            providers.push(Provider::File(file));
+        }
+
+        for (archive, content) in self.archives.iter() {
+            if content.get_ignore_case(path).is_some() {
+                providers.push(Provider::Archive(archive.clone()));
+            }
+        }
+
+        providers
+    }
+
+    /// The provider renpy uses to load `path`, if any.
+    pub fn resolve(&self, path: &ArchivePath) -> Option<Provider> {
+        self.providers(path).into_iter().next()
+    }
+
+    /// Every path provided more than once mapped to its providers in the order renpy
+    /// checks them.
+    ///
+    /// Paths are grouped ignoring case. Loose files with names that are not valid UTF-8
+    /// are skipped, as are linked directories.
+    ///
+    /// # Errors
+    ///
+    /// This function forwards errors raised while walking the game directory.
+    pub fn shadowed(&self) -> RpaResult<BTreeMap<ArchivePath, Vec<Provider>>> {
+        let mut providers: BTreeMap<String, (ArchivePath, Vec<Provider>)> = BTreeMap::new();
+        let mut push = |path: ArchivePath, provider| {
+            providers
+                .entry(path.fold())
+                .or_insert_with(|| (path, Vec::new()))
+                .1
+                .push(provider)
+        };
+
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let file = entry.path();
+
+                // Links to directories are not followed, which also avoids loops.
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    dirs.push(file);
+                } else if file_type.is_symlink() && file.is_dir() {
+                    debug!("Skipping linked directory: {}", file.display());
+                } else if let Ok(path) = file.strip_prefix(&self.root) {
+                    match ArchivePath::from_path(path) {
+                        Ok(path) => push(path, Provider::File(file.clone())),
+                        Err(e) => debug!("Skipping loose file: {e}"),
+                    }
+                }
+            }
+        }
+
+        for (archive, content) in self.archives.iter() {
+            for path in content.keys() {
+                push(path.clone(), Provider::Archive(archive.clone()));
+            }
+        }
+
+        Ok(providers
+            .into_values()
+            .filter(|(_, providers)| providers.len() > 1)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    /// Create a game directory with a loose file and two archives.
+    fn game_dir(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("warpalib-set-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("images")).unwrap();
+        fs::write(root.join("script.rpy"), "label start:").unwrap();
+
+        let archives: [(&str, &[&str]); 2] = [
+            ("a.rpa", &["script.rpy", "images/bg.png", "audio.ogg"]),
+            ("b.rpa", &["images/BG.png", "music.ogg"]),
+        ];
+        for (name, paths) in archives {
+            let mut archive = RenpyArchive::new();
+            for path in paths {
+                archive.content.insert_raw(*path, name.as_bytes().to_vec());
+            }
+            archive
+                .flush(&mut File::create(root.join(name)).unwrap())
+                .unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn should_resolve_files_before_archives_in_reverse_name_order() {
+        let root = game_dir("resolve");
+        let set = ArchiveSet::open(&root).unwrap();
+
+        let archives = set
+            .archives
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(archives, [root.join("b.rpa"), root.join("a.rpa")]);
+
+        assert_eq!(
+            set.providers(&"script.rpy".into()),
+            [
+                Provider::File(root.join("script.rpy")),
+                Provider::Archive(root.join("a.rpa")),
+            ]
+        );
+        assert_eq!(
+            set.resolve(&"images/bg.png".into()),
+            Some(Provider::Archive(root.join("b.rpa")))
+        );
+        assert_eq!(
+            set.resolve(&"audio.ogg".into()),
+            Some(Provider::Archive(root.join("a.rpa")))
+        );
+        assert_eq!(set.resolve(&"missing.png".into()), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn should_list_shadowed_paths() {
+        let root = game_dir("shadowed");
+        let set = ArchiveSet::open(&root).unwrap();
+
+        let shadowed = set.shadowed().unwrap();
+        let paths = shadowed
+            .keys()
+            .map(|path| path.as_str())
+            .collect::<Vec<_>>();
+        // Paths are spelled as by their first provider.
+        assert_eq!(paths, ["images/BG.png", "script.rpy"]);
+        assert_eq!(
+            shadowed[&ArchivePath::from("images/BG.png")],
+            [
+                Provider::Archive(root.join("b.rpa")),
+                Provider::Archive(root.join("a.rpa")),
+            ]
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_not_follow_directory_links() {
+        let root = game_dir("links");
+        std::os::unix::fs::symlink(&root, root.join("images/loop")).unwrap();
+
+        let set = ArchiveSet::open(&root).unwrap();
+        let shadowed = set.shadowed().unwrap();
+        assert_eq!(shadowed.len(), 2);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,46 +1,97 @@
 use std::{
+    cmp::Ordering,
     collections::{hash_map, HashMap},
-    fs::File,
-    io::{self, Cursor, Read, Seek, Write},
+    env,
+    fmt::Debug,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, PoisonError},
 };
 
 use log::debug;
 
-use crate::{Record, RpaError, RpaResult};
+use crate::{
+    copy::{copy_chunked, copy_section_chunked, write_chunked, Between},
+    index::IndexTable,
+    temp::TempFile,
+    ArchivePath, ContentSource, Entry, Record, RpaError, RpaResult,
+};
 
 /// Represents contents of an archive mapped to their path
-#[derive(Default, Debug)]
-pub struct ContentMap(HashMap<PathBuf, Content>);
+///
+/// Maps read from an archive keep the decoded index in a compact table, and only build
+/// the map and its records when the content is first accessed.
+#[derive(Default)]
+pub struct ContentMap {
+    map: OnceLock<HashMap<ArchivePath, Content>>,
+    index: Mutex<Option<IndexTable>>,
+}
 
-impl From<HashMap<PathBuf, Content>> for ContentMap {
-    fn from(value: HashMap<PathBuf, Content>) -> Self {
-        ContentMap(value)
+impl From<HashMap<ArchivePath, Content>> for ContentMap {
+    fn from(value: HashMap<ArchivePath, Content>) -> Self {
+        ContentMap {
+            map: OnceLock::from(value),
+            index: Mutex::default(),
+        }
+    }
+}
+
+impl Debug for ContentMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ContentMap").field(self.map()).finish()
     }
 }
 
 impl Deref for ContentMap {
-    type Target = HashMap<PathBuf, Content>;
+    type Target = HashMap<ArchivePath, Content>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        self.map()
     }
 }
 
 impl DerefMut for ContentMap {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        self.map_mut()
     }
 }
 
 impl IntoIterator for ContentMap {
-    type Item = (PathBuf, Content);
+    type Item = (ArchivePath, Content);
+
+    type IntoIter = hash_map::IntoIter<ArchivePath, Content>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        mem::take(self.map_mut()).into_iter()
+    }
+}
+
+impl ContentMap {
+    /// Create a map holding the decoded `index` until it is first accessed.
+    pub(crate) fn from_index(index: IndexTable) -> Self {
+        ContentMap {
+            map: OnceLock::new(),
+            index: Mutex::new(Some(index)),
+        }
+    }
 
-    type IntoIter = hash_map::IntoIter<PathBuf, Content>;
+    fn map(&self) -> &HashMap<ArchivePath, Content> {
+        self.map.get_or_init(|| {
+            let index = self
+                .index
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            index.map(IndexTable::into_map).unwrap_or_default()
+        })
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+    fn map_mut(&mut self) -> &mut HashMap<ArchivePath, Content> {
+        self.map();
+        self.map.get_mut().expect("map was just built")
     }
 }
 
@@ -49,12 +100,17 @@ impl ContentMap {
     /// archive with the same path.
     ///
     /// The data is not written into the archive until `flush` is called.
-    pub fn insert_file<P>(&mut self, path: P) -> Option<Content>
+    ///
+    /// # Errors
+    ///
+    /// This function returns `NonUtf8Path` if `path` is not valid UTF-8.
+    pub fn insert_file<P>(&mut self, path: P) -> RpaResult<Option<Content>>
     where
         P: Into<PathBuf>,
     {
-        fn inner(map: &mut ContentMap, path: PathBuf) -> Option<Content> {
-            map.0.insert(path.clone(), Content::File(path))
+        fn inner(map: &mut ContentMap, path: PathBuf) -> RpaResult<Option<Content>> {
+            let key = ArchivePath::from_path(&path)?;
+            Ok(map.map_mut().insert(key, Content::File(path)))
         }
         inner(self, path.into())
     }
@@ -66,12 +122,13 @@ impl ContentMap {
     /// in filesystem.
     ///
     /// The data is not written into the archive until `flush` is called.
-    pub fn insert_file_mapped<P>(&mut self, archive_path: P, file_path: P) -> Option<Content>
+    pub fn insert_file_mapped<A, P>(&mut self, archive_path: A, file_path: P) -> Option<Content>
     where
+        A: Into<ArchivePath>,
         P: Into<PathBuf>,
     {
-        fn inner(map: &mut ContentMap, key: PathBuf, value: PathBuf) -> Option<Content> {
-            map.0.insert(key, Content::File(value))
+        fn inner(map: &mut ContentMap, key: ArchivePath, value: PathBuf) -> Option<Content> {
+            map.map_mut().insert(key, Content::File(value))
         }
         inner(self, archive_path.into(), file_path.into())
     }
@@ -81,14 +138,71 @@ impl ContentMap {
     /// The data is not written into the archive until `flush` is called.
     pub fn insert_raw<P>(&mut self, path: P, bytes: Vec<u8>) -> Option<Content>
     where
-        P: Into<PathBuf>,
+        P: Into<ArchivePath>,
     {
-        fn inner(map: &mut ContentMap, path: PathBuf, bytes: Vec<u8>) -> Option<Content> {
-            map.0.insert(path, Content::Raw(bytes))
+        fn inner(map: &mut ContentMap, path: ArchivePath, bytes: Vec<u8>) -> Option<Content> {
+            map.map_mut().insert(path, Content::Raw(bytes))
         }
         inner(self, path.into(), bytes)
     }
 
+    /// Add a source of data to the archive.
+    ///
+    /// The source is not read until `flush` is called.
+    pub fn insert_source<P, S>(&mut self, path: P, source: S) -> Option<Content>
+    where
+        P: Into<ArchivePath>,
+        S: ContentSource + 'static,
+    {
+        self.map_mut()
+            .insert(path.into(), Content::Source(Box::new(source)))
+    }
+
+    /// Return a writer creating the content of `path` incrementally.
+    ///
+    /// The written data is spooled to a temp file rather than kept in memory, and is
+    /// added to the map once [`EntryWriter::finish`] is called, replacing any existing
+    /// content. The temp file is removed along with the content, or when the writer is
+    /// dropped without finishing.
+    ///
+    /// Use [`ContentMap::insert_source`] instead to read the data straight into the
+    /// archive when it is written.
+    ///
+    /// # Errors
+    ///
+    /// This function forwards errors from creating the temp file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    /// use warpalib::RenpyArchive;
+    ///
+    /// let mut archive = RenpyArchive::new();
+    ///
+    /// let mut writer = archive.content.writer("log.txt").unwrap();
+    /// write!(writer, "line 1\n").unwrap();
+    /// write!(writer, "line 2\n").unwrap();
+    /// writer.finish().unwrap();
+    ///
+    /// let mut data = vec![];
+    /// archive.copy_file("log.txt", &mut data).unwrap();
+    /// assert_eq!(data, b"line 1\nline 2\n");
+    /// ```
+    pub fn writer<P>(&mut self, path: P) -> io::Result<EntryWriter<'_>>
+    where
+        P: Into<ArchivePath>,
+    {
+        let (temp, file) = TempFile::create(&env::temp_dir().join("warpalib-entry"))?;
+        Ok(EntryWriter {
+            map: self,
+            path: path.into(),
+            temp,
+            file: BufWriter::new(file),
+            length: 0,
+        })
+    }
+
     /// Change path of content without changing content itself, replacing any existing content
     /// in the new path.
     ///
@@ -97,7 +211,6 @@ impl ContentMap {
     /// # Examples
     ///
     /// ```rust
-    /// use std::path::Path;
     /// use warpalib::{Content, ContentMap};
     ///
     /// // Create empty map and populate with initial values.
@@ -110,34 +223,346 @@ impl ContentMap {
     /// assert_eq!(old_content, Some(Content::Raw(vec![4, 5, 6])));
     ///
     /// // file2 is replaced with file1 content.
-    /// let new_content = map.get(Path::new("file2.txt"));
+    /// let new_content = map.get("file2.txt");
     /// assert_eq!(new_content, Some(&Content::Raw(vec![1, 2, 3])));
     ///
     /// // file1 no longer exists.
-    /// let file1 = map.get(Path::new("file1.txt"));
+    /// let file1 = map.get("file1.txt");
     /// assert_eq!(file1, None);
     /// ```
     pub fn rename_key<O, N>(&mut self, old_path: O, new_path: N) -> RpaResult<Option<Content>>
     where
-        O: AsRef<Path>,
-        N: Into<PathBuf>,
+        O: Into<ArchivePath>,
+        N: Into<ArchivePath>,
     {
         fn inner(
             map: &mut ContentMap,
-            old_path: &Path,
-            new_path: PathBuf,
+            old_path: ArchivePath,
+            new_path: ArchivePath,
         ) -> RpaResult<Option<Content>> {
-            match map.remove(old_path) {
+            match map.remove(&old_path) {
                 Some(content) => Ok(map.insert(new_path, content)),
-                None => Err(RpaError::NotFound(old_path.to_path_buf())),
+                None => Err(RpaError::NotFound(old_path)),
+            }
+        }
+        inner(self, old_path.into(), new_path.into())
+    }
+
+    /// Return the content of `path`.
+    ///
+    /// The path is normalized like any [`ArchivePath`], so `images\\bg.png` or a
+    /// decomposed (NFD) name find the same content.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{Content, ContentMap};
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("images/caf\u{e9}.png", vec![1]);
+    ///
+    /// assert_eq!(map.get("images\\cafe\u{301}.png"), Some(&Content::Raw(vec![1])));
+    /// ```
+    pub fn get<P>(&self, path: P) -> Option<&Content>
+    where
+        P: Into<ArchivePath>,
+    {
+        self.map().get(&path.into())
+    }
+
+    /// Return the content of `path` mutably, normalizing the path like
+    /// [`ContentMap::get`].
+    pub fn get_mut<P>(&mut self, path: P) -> Option<&mut Content>
+    where
+        P: Into<ArchivePath>,
+    {
+        self.map_mut().get_mut(&path.into())
+    }
+
+    /// Whether the map holds content at `path`, normalizing the path like
+    /// [`ContentMap::get`].
+    pub fn contains_key<P>(&self, path: P) -> bool
+    where
+        P: Into<ArchivePath>,
+    {
+        self.map().contains_key(&path.into())
+    }
+
+    /// Remove and return the content at `path`, normalizing the path like
+    /// [`ContentMap::get`].
+    pub fn remove<P>(&mut self, path: P) -> Option<Content>
+    where
+        P: Into<ArchivePath>,
+    {
+        self.map_mut().remove(&path.into())
+    }
+
+    /// Return the content of `path` ignoring case, the way renpy resolves names.
+    ///
+    /// An exact match is preferred. Otherwise, every path is compared which makes this
+    /// slower than [`ContentMap::get`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{ArchivePath, ContentMap};
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("images/Eileen.png", vec![]);
+    ///
+    /// let (path, _) = map.get_ignore_case("images/eileen.png").unwrap();
+    /// assert_eq!(path, &ArchivePath::from("images/Eileen.png"));
+    /// ```
+    pub fn get_ignore_case<P>(&self, path: P) -> Option<(&ArchivePath, &Content)>
+    where
+        P: Into<ArchivePath>,
+    {
+        fn inner(map: &ContentMap, path: ArchivePath) -> Option<(&ArchivePath, &Content)> {
+            if let Some(entry) = map.map().get_key_value(&path) {
+                return Some(entry);
             }
+
+            let folded = path.fold();
+            map.iter().find(|(key, _)| key.fold() == folded)
         }
-        inner(self, old_path.as_ref(), new_path.into())
+        inner(self, path.into())
+    }
+
+    /// Convert every [`Content::Record`] into a [`Content::ArchiveRecord`] pointing
+    /// to the archive file at `archive`.
+    ///
+    /// This detaches the content from the reader of the archive it was read from,
+    /// allowing it to be mixed with content of other archives.
+    pub fn into_external<P>(self, archive: P) -> ContentMap
+    where
+        P: Into<PathBuf>,
+    {
+        fn inner(map: ContentMap, archive: PathBuf) -> ContentMap {
+            map.into_iter()
+                .map(|(path, content)| match content {
+                    Content::Record(record) => (
+                        path,
+                        Content::ArchiveRecord {
+                            archive: archive.clone(),
+                            record,
+                        },
+                    ),
+                    content => (path, content),
+                })
+                .collect::<HashMap<_, _>>()
+                .into()
+        }
+        inner(self, archive.into())
+    }
+
+    /// Return the content ordered by where its data is stored.
+    ///
+    /// Records are ordered by their start offset, so reading them one after another
+    /// moves through the archive sequentially. See [`Content::cmp_offset`] for the
+    /// full order. Content stored at the same position is ordered by path.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{Content, ContentMap, Record};
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert("b.txt".into(), Content::Record(Record::new(40, 4, None, None)));
+    /// map.insert("a.txt".into(), Content::Record(Record::new(80, 4, None, None)));
+    /// map.insert_raw("c.txt", vec![]);
+    ///
+    /// let paths = map
+    ///     .sorted_by_offset()
+    ///     .into_iter()
+    ///     .map(|(path, _)| path.as_str())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(paths, ["b.txt", "a.txt", "c.txt"]);
+    /// ```
+    pub fn sorted_by_offset(&self) -> Vec<(&ArchivePath, &Content)> {
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, x), (b, y)| x.cmp_offset(y).then_with(|| a.cmp(b)));
+        entries
+    }
+
+    /// Consume the map and return the content ordered by where its data is stored,
+    /// like [`ContentMap::sorted_by_offset`].
+    pub fn into_sorted_by_offset(self) -> Vec<(ArchivePath, Content)> {
+        let mut entries = self.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, x), (b, y)| x.cmp_offset(y).then_with(|| a.cmp(b)));
+        entries
+    }
+
+    /// Return a view of every entry of the map, in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> {
+        self.iter().map(|(path, content)| Entry::new(path, content))
+    }
+
+    /// The size of the data written by [`RenpyArchive::flush`], the sum of the length
+    /// of all content. The header and index add a few bytes per entry on top.
+    ///
+    /// [`RenpyArchive::flush`]: crate::RenpyArchive::flush
+    ///
+    /// # Errors
+    ///
+    /// This function returns the errors of [`Content::length`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::ContentMap;
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("a.txt", vec![1, 2, 3]);
+    /// map.insert_raw("b.txt", vec![4, 5]);
+    ///
+    /// assert_eq!(map.total_size().unwrap(), 5);
+    /// ```
+    pub fn total_size(&self) -> io::Result<u64> {
+        self.values().map(Content::length).sum()
+    }
+
+    /// Move all content from `other` into this map, resolving paths present in
+    /// both maps using `policy`.
+    ///
+    /// Take care to detach `other` using [`ContentMap::into_external`] if it
+    /// contains records from another archive.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `DuplicatePath` if `policy` is [`MergePolicy::Error`] and
+    /// a path exists in both maps. Content merged before the duplicate is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{Content, ContentMap, MergePolicy};
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("script.rpy", vec![1]);
+    ///
+    /// let mut patch = ContentMap::default();
+    /// patch.insert_raw("script.rpy", vec![2]);
+    ///
+    /// map.merge(patch, MergePolicy::LastWins).unwrap();
+    /// assert_eq!(map.get("script.rpy"), Some(&Content::Raw(vec![2])));
+    /// ```
+    pub fn merge(&mut self, other: ContentMap, policy: MergePolicy) -> RpaResult<()> {
+        for (path, content) in other.into_iter() {
+            match policy {
+                MergePolicy::LastWins => {
+                    if self.insert(path.clone(), content).is_some() {
+                        debug!("Replaced content in path ({path})");
+                    }
+                }
+                MergePolicy::FirstWins => match self.entry(path) {
+                    hash_map::Entry::Occupied(entry) => {
+                        debug!("Kept existing content in path ({})", entry.key());
+                    }
+                    hash_map::Entry::Vacant(entry) => {
+                        entry.insert(content);
+                    }
+                },
+                MergePolicy::Error => match self.entry(path) {
+                    hash_map::Entry::Occupied(entry) => {
+                        return Err(RpaError::DuplicatePath(entry.key().clone()));
+                    }
+                    hash_map::Entry::Vacant(entry) => {
+                        entry.insert(content);
+                    }
+                },
+            }
+        }
+
+        Ok(())
     }
 }
 
+/// A writer spooling the data of an entry to a temp file, added to a [`ContentMap`]
+/// when finished.
+///
+/// Created using [`ContentMap::writer`].
+#[derive(Debug)]
+pub struct EntryWriter<'m> {
+    map: &'m mut ContentMap,
+    path: ArchivePath,
+    temp: TempFile,
+    file: BufWriter<File>,
+    length: u64,
+}
+
+impl EntryWriter<'_> {
+    /// Add the written data to the map, returning the content it replaced.
+    ///
+    /// # Errors
+    ///
+    /// This function forwards errors from writing the buffered data to the temp file,
+    /// in which case the data is discarded.
+    pub fn finish(self) -> io::Result<Option<Content>> {
+        self.file.into_inner().map_err(|e| e.into_error())?;
+        debug!(
+            "Adding written content ({} bytes) to path ({})",
+            self.length, self.path
+        );
+
+        let spool = Spool {
+            temp: self.temp,
+            length: self.length,
+        };
+        Ok(self
+            .map
+            .map_mut()
+            .insert(self.path, Content::Source(Box::new(spool))))
+    }
+}
+
+impl Write for EntryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.length += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// The data of a finished [`EntryWriter`], removed along with the content.
+struct Spool {
+    temp: TempFile,
+    length: u64,
+}
+
+impl ContentSource for Spool {
+    fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(File::open(self.temp.path())?))
+    }
+
+    fn length(&self) -> Option<u64> {
+        Some(self.length)
+    }
+}
+
+/// Defines how paths present in both maps are resolved when merging.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Content being merged replaces existing content.
+    #[default]
+    LastWins,
+
+    /// Existing content is kept.
+    FirstWins,
+
+    /// Merging fails on the first duplicate path.
+    Error,
+}
+
 /// Represents data stored in archive.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// New kinds of content may be added in minor releases, so matches outside this
+/// crate need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
 pub enum Content {
     /// Points to a slice in archive.
     Record(Record),
@@ -147,14 +572,90 @@ pub enum Content {
 
     /// Bytes in memory.
     Raw(Vec<u8>),
+
+    /// Points to a slice in another archive file.
+    ArchiveRecord {
+        /// Path to the archive file holding the data.
+        archive: PathBuf,
+
+        /// Location of the data in `archive`.
+        record: Record,
+    },
+
+    /// Data read from a custom source.
+    Source(Box<dyn ContentSource>),
+}
+
+impl PartialEq for Content {
+    /// Sources are only equal to themselves since their data is not known.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Content::Record(a), Content::Record(b)) => a == b,
+            (Content::File(a), Content::File(b)) => a == b,
+            (Content::Raw(a), Content::Raw(b)) => a == b,
+            (
+                Content::ArchiveRecord {
+                    archive: a,
+                    record: ra,
+                },
+                Content::ArchiveRecord {
+                    archive: b,
+                    record: rb,
+                },
+            ) => a == b && ra == rb,
+            (Content::Source(a), Content::Source(b)) => std::ptr::addr_eq(a.as_ref(), b.as_ref()),
+            _ => false,
+        }
+    }
 }
 
+impl Eq for Content {}
+
 impl Content {
+    /// The number of bytes this content occupies when written to an archive.
+    ///
+    /// # Errors
+    ///
+    /// This function forwards errors from reading the metadata of `File` content and
+    /// returns an error for `Source` content without a known length.
+    pub fn length(&self) -> io::Result<u64> {
+        match self {
+            Content::Record(record) | Content::ArchiveRecord { record, .. } => Ok(record.length),
+            Content::File(path) => Ok(fs::metadata(path)?.len()),
+            Content::Raw(data) => Ok(data.len() as u64),
+            Content::Source(source) => source
+                .length()
+                .ok_or_else(|| io::Error::other("length of content source is unknown")),
+        }
+    }
+
+    /// Compare where the data of the content is stored, to read content in an order
+    /// that avoids seeking back and forth.
+    ///
+    /// Records of the archive come first ordered by their start offset, followed by
+    /// records of other archives ordered by archive and start offset. Other content
+    /// is stored separately and compares equal.
+    pub fn cmp_offset(&self, other: &Self) -> Ordering {
+        fn key(content: &Content) -> (u8, Option<&Path>, u64) {
+            match content {
+                Content::Record(record) => (0, None, record.start),
+                Content::ArchiveRecord { archive, record } => (1, Some(archive), record.start),
+                _ => (2, None, 0),
+            }
+        }
+        key(self).cmp(&key(other))
+    }
+
     /// Copy data from the content into the `writer`.
     ///
     /// - `Record` - Data is copied from the archive (reader).
     /// - `File` - Data is copied from the file.
     /// - `Raw` - Raw in-memory buffer is copied.
+    /// - `ArchiveRecord` - Data is copied from the other archive file.
+    /// - `Source` - Data is copied from a newly opened reader of the source.
+    ///
+    /// On Linux, records, files and records of other archives are copied within the
+    /// kernel when `writer` is a file, see [`Record::copy_section`].
     ///
     /// # Examples
     ///
@@ -174,24 +675,128 @@ impl Content {
     /// assert_eq!(bytes, buffer);
     /// ```
     pub fn copy_to<R, W>(&self, reader: &mut R, writer: &mut W) -> io::Result<u64>
+    where
+        R: Seek + Read,
+        W: Write,
+    {
+        self.copy_chunked(reader, writer, &mut |_| Ok(()))
+    }
+
+    /// Copy the content like [`Content::copy_to`], calling `between` with the bytes
+    /// copied after each chunk.
+    pub(crate) fn copy_chunked<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        between: &mut Between,
+    ) -> io::Result<u64>
     where
         R: Seek + Read,
         W: Write,
     {
         match self {
-            Content::Record(record) => record.copy_section(reader, writer),
+            Content::Record(record) => copy_section_chunked(record, reader, writer, between),
             Content::File(path) => {
                 debug!("Copying file content: {}", path.display());
 
                 let mut file = File::open(path)?;
-                io::copy(&mut file, writer)
+                copy_chunked(&mut file, writer, between)
             }
             Content::Raw(data) => {
                 debug!("Copying raw content: {} bytes", data.len());
 
-                let mut cursor = Cursor::new(data);
-                io::copy(&mut cursor, writer)
+                write_chunked(data, writer, between)
+            }
+            Content::ArchiveRecord { archive, record } => {
+                debug!("Copying record from archive: {}", archive.display());
+
+                let mut reader = BufReader::new(File::open(archive)?);
+                copy_section_chunked(record, &mut reader, writer, between)
+            }
+            Content::Source(source) => {
+                debug!("Copying source content: {:?} bytes", source.length());
+
+                let mut reader = source.open()?;
+                copy_chunked(&mut reader, writer, between)
+            }
+        }
+    }
+
+    /// Open a reader of the data of the content, like [`Content::copy_to`].
+    ///
+    /// Records are read from the archive `reader`, which the returned reader borrows.
+    ///
+    /// # Errors
+    ///
+    /// This function forwards errors from opening files and sources and seeking to
+    /// records.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::{Cursor, Read};
+    /// use warpalib::{Content, Record};
+    ///
+    /// let mut reader = Cursor::new(vec![1, 2, 3, 4]);
+    /// let content = Content::Record(Record::new(2, 3, Some(vec![9]), None));
+    ///
+    /// let mut data = vec![];
+    /// content.open(&mut reader).unwrap().read_to_end(&mut data).unwrap();
+    /// assert_eq!(data, vec![9, 3, 4]);
+    /// ```
+    pub fn open<'r, R>(&'r self, reader: &'r mut R) -> io::Result<Box<dyn Read + 'r>>
+    where
+        R: Seek + Read,
+    {
+        match self {
+            Content::Record(record) => {
+                let prefix = record.prefix.as_deref().unwrap_or_default();
+                Ok(Box::new(prefix.chain(record.scope(reader)?)))
             }
+            Content::File(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+            Content::Raw(data) => Ok(Box::new(&data[..])),
+            Content::ArchiveRecord { archive, record } => {
+                let mut reader = BufReader::new(File::open(archive)?);
+                reader.seek(SeekFrom::Start(record.start))?;
+
+                let prefix = record.prefix.as_deref().unwrap_or_default();
+                Ok(Box::new(prefix.chain(reader.take(record.actual_length()))))
+            }
+            Content::Source(source) => source.open(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writer_should_spool_until_content_is_removed() {
+        let mut map = ContentMap::default();
+
+        let mut writer = map.writer("a.txt").unwrap();
+        let spool = writer.temp.path().to_path_buf();
+        writer.write_all(b"hello").unwrap();
+        assert!(writer.finish().unwrap().is_none());
+
+        assert!(spool.exists());
+        assert_eq!(map.get("a.txt").unwrap().length().unwrap(), 5);
+
+        map.remove("a.txt");
+        assert!(!spool.exists());
+    }
+
+    #[test]
+    fn dropped_writer_should_discard_data() {
+        let mut map = ContentMap::default();
+
+        let mut writer = map.writer("a.txt").unwrap();
+        let spool = writer.temp.path().to_path_buf();
+        writer.write_all(b"hello").unwrap();
+        drop(writer);
+
+        assert!(!spool.exists());
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use log::trace;
+
+use crate::{PositionalReader, Record};
+
+/// Bytes copied between checking cancellation and reporting progress.
+pub(crate) const CHUNK: u64 = 8 << 20;
+
+/// A callback receiving the bytes copied after each chunk, failing to stop the copy.
+pub(crate) type Between<'b> = dyn FnMut(u64) -> io::Result<()> + 'b;
+
+/// Copy the `reader` into the `writer` in chunks, calling `between` after each one.
+///
+/// Each chunk is copied with [`io::copy`], so copies between files keep its
+/// specializations.
+pub(crate) fn copy_chunked<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    between: &mut Between,
+) -> io::Result<u64>
+where
+    R: Read,
+    W: Write,
+{
+    let mut copied = 0;
+    loop {
+        let chunk = io::copy(&mut reader.by_ref().take(CHUNK), writer)?;
+        if chunk == 0 {
+            return Ok(copied);
+        }
+        copied += chunk;
+        between(chunk)?;
+    }
+}
+
+/// Write the `data` into the `writer` in chunks, calling `between` after each one.
+pub(crate) fn write_chunked<W: Write>(
+    data: &[u8],
+    writer: &mut W,
+    between: &mut Between,
+) -> io::Result<u64> {
+    for chunk in data.chunks(CHUNK as usize) {
+        writer.write_all(chunk)?;
+        between(chunk.len() as u64)?;
+    }
+    Ok(data.len() as u64)
+}
+
+/// Copy the data of `record` from the archive `reader` into the `writer` in chunks,
+/// calling `between` after each one, like [`Record::copy_section`].
+pub(crate) fn copy_section_chunked<R, W>(
+    record: &Record,
+    reader: &mut R,
+    writer: &mut W,
+    between: &mut Between,
+) -> io::Result<u64>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut written = 0;
+    if let Some(prefix) = record.prefix.as_ref() {
+        written += write_chunked(prefix, writer, between)?;
+    }
+
+    Ok(written + copy_chunked(&mut record.scope(reader)?, writer, between)?)
+}
+
+/// Copy the data of `record` from the archive `source` to the position of `target`,
+/// calling `between` after each chunk.
+///
+/// On Linux, the data is copied within the kernel using `copy_file_range` with
+/// explicit offsets, so `source` can be shared between threads. Otherwise, or when
+/// the files do not support it, the data is copied with positional reads.
+pub(crate) fn copy_record(
+    source: &File,
+    record: &Record,
+    target: &mut File,
+    between: &mut Between,
+) -> io::Result<u64> {
+    let mut written = 0;
+    if let Some(prefix) = record.prefix.as_ref() {
+        written += write_chunked(prefix, target, between)?;
+    }
+
+    let length = record.actual_length();
+    let copied = copy_range(source, record.start, length, target, between)?;
+    if copied != length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    Ok(written + copied)
+}
+
+/// Copy `length` bytes at `offset` of `source` to `target` within the kernel.
+#[cfg(target_os = "linux")]
+fn copy_range(
+    source: &File,
+    offset: u64,
+    length: u64,
+    target: &mut File,
+    between: &mut Between,
+) -> io::Result<u64> {
+    use std::os::fd::AsRawFd;
+
+    let start = target.stream_position()?;
+    let mut copied = 0;
+    while copied < length {
+        let chunk = (length - copied).min(CHUNK) as usize;
+        let mut in_offset = (offset + copied) as libc::loff_t;
+        let mut out_offset = (start + copied) as libc::loff_t;
+
+        // SAFETY: Both descriptors are open for the duration of the call and the
+        // offsets point to valid integers.
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                &mut in_offset,
+                target.as_raw_fd(),
+                &mut out_offset,
+                chunk,
+                0,
+            )
+        };
+
+        match result {
+            // The end of the source.
+            0 => break,
+            n if n > 0 => {
+                copied += n as u64;
+                between(n as u64)?;
+            }
+            _ => {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // Not supported by the kernel or between these files.
+                    Some(
+                        libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM,
+                    ) => {
+                        trace!("Falling back from copy_file_range: {e}");
+                        target.seek(SeekFrom::Start(start + copied))?;
+                        let rest = copy_positional(
+                            source,
+                            offset + copied,
+                            length - copied,
+                            target,
+                            between,
+                        )?;
+                        return Ok(copied + rest);
+                    }
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+
+    // The file position is not moved by copies with explicit offsets.
+    target.seek(SeekFrom::Start(start + copied))?;
+    Ok(copied)
+}
+
+/// Copy `length` bytes at `offset` of `source` to `target` with positional reads.
+#[cfg(not(target_os = "linux"))]
+fn copy_range(
+    source: &File,
+    offset: u64,
+    length: u64,
+    target: &mut File,
+    between: &mut Between,
+) -> io::Result<u64> {
+    copy_positional(source, offset, length, target, between)
+}
+
+fn copy_positional(
+    source: &File,
+    offset: u64,
+    length: u64,
+    target: &mut File,
+    between: &mut Between,
+) -> io::Result<u64> {
+    let mut reader = PositionalReader::new(source, offset + length);
+    reader.seek(SeekFrom::Start(offset))?;
+    copy_chunked(&mut reader.take(length), target, between)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn temp_file(name: &str, data: &[u8]) -> (std::path::PathBuf, File) {
+        let path =
+            std::env::temp_dir().join(format!("warpalib-copy-{}-{name}", std::process::id()));
+        fs::write(&path, data).unwrap();
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn should_copy_record_after_target_position() {
+        let (source_path, source) = temp_file("source", b"....hello world");
+        let (target_path, mut target) = temp_file("target", b"head");
+        target.seek(SeekFrom::End(0)).unwrap();
+
+        let record = Record::new(4, 7, Some(b"> ".to_vec()), None);
+        let mut reported = 0;
+        let written = copy_record(&source, &record, &mut target, &mut |bytes| {
+            reported += bytes;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!((written, reported), (7, 7));
+
+        // The position follows the copied data.
+        target.write_all(b"!").unwrap();
+        assert_eq!(fs::read(&target_path).unwrap(), b"head> hello!");
+
+        fs::remove_file(source_path).unwrap();
+        fs::remove_file(target_path).unwrap();
+    }
+
+    #[test]
+    fn should_fail_records_past_the_end() {
+        let (source_path, source) = temp_file("short-source", b"....hello");
+        let (target_path, mut target) = temp_file("short-target", b"");
+
+        let record = Record::new(4, 10, None, None);
+        let result = copy_record(&source, &record, &mut target, &mut |_| Ok(()));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        fs::remove_file(source_path).unwrap();
+        fs::remove_file(target_path).unwrap();
+    }
+
+    #[test]
+    fn should_copy_positional_in_chunks() {
+        let data = vec![7u8; CHUNK as usize * 2 + 1];
+        let (source_path, source) = temp_file("positional-source", &data);
+        let (target_path, mut target) = temp_file("positional-target", b"");
+
+        let mut chunks = vec![];
+        let copied = copy_positional(&source, 1, data.len() as u64 - 1, &mut target, &mut |b| {
+            chunks.push(b);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(copied, data.len() as u64 - 1);
+        assert_eq!(chunks, [CHUNK, CHUNK]);
+        assert_eq!(fs::read(&target_path).unwrap(), data[1..]);
+
+        fs::remove_file(source_path).unwrap();
+        fs::remove_file(target_path).unwrap();
+    }
+
+    #[test]
+    fn should_stop_when_between_fails() {
+        let data = vec![7u8; CHUNK as usize * 2];
+        let (source_path, source) = temp_file("stop-source", &data);
+        let (target_path, mut target) = temp_file("stop-target", b"");
+
+        let record = Record::new(0, data.len() as u64, None, None);
+        let result = copy_record(&source, &record, &mut target, &mut |_| {
+            Err(io::Error::other("stop"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::metadata(&target_path).unwrap().len(), CHUNK);
+
+        fs::remove_file(source_path).unwrap();
+        fs::remove_file(target_path).unwrap();
+    }
+}
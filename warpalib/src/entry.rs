@@ -0,0 +1,128 @@
+use std::{
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+use crate::{ArchivePath, Content};
+
+/// The kind of source the data of an [`Entry`] is read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A slice of the archive.
+    Record,
+
+    /// A file in the storage.
+    File,
+
+    /// Bytes in memory.
+    Raw,
+
+    /// A slice of another archive file.
+    ArchiveRecord,
+
+    /// A custom source.
+    Source,
+}
+
+/// A view of a file in an archive, see [`ContentMap::entries`].
+///
+/// [`ContentMap::entries`]: crate::ContentMap::entries
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::{Cursor, Read};
+/// use warpalib::{EntryKind, RenpyArchive};
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+/// let mut archive = archive.flush_into(Cursor::new(vec![]), Ok).unwrap();
+/// archive.content.insert_raw("b.txt", b"world!".to_vec());
+///
+/// assert_eq!(archive.content.total_size().unwrap(), 11);
+///
+/// // Iterate the map itself to read the entries from the archive reader.
+/// for entry in archive.content.entries() {
+///     let mut data = String::new();
+///     let mut reader = entry.open(&mut archive.reader).unwrap();
+///     reader.read_to_string(&mut data).unwrap();
+///
+///     match entry.kind() {
+///         EntryKind::Record => assert_eq!(data, "hello"),
+///         _ => assert_eq!(data, "world!"),
+///     }
+///     assert_eq!(entry.size().unwrap(), data.len() as u64);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    path: &'a ArchivePath,
+    content: &'a Content,
+}
+
+impl<'a> Entry<'a> {
+    pub(crate) fn new(path: &'a ArchivePath, content: &'a Content) -> Self {
+        Self { path, content }
+    }
+
+    /// The path of the entry in the archive.
+    pub fn path(&self) -> &'a ArchivePath {
+        self.path
+    }
+
+    /// The content of the entry.
+    pub fn content(&self) -> &'a Content {
+        self.content
+    }
+
+    /// The kind of source the data is read from.
+    pub fn kind(&self) -> EntryKind {
+        match self.content {
+            Content::Record(_) => EntryKind::Record,
+            Content::File(_) => EntryKind::File,
+            Content::Raw(_) => EntryKind::Raw,
+            Content::ArchiveRecord { .. } => EntryKind::ArchiveRecord,
+            Content::Source(_) => EntryKind::Source,
+        }
+    }
+
+    /// The size of the data when read, including the prefix of records.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the errors of [`Content::length`].
+    pub fn size(&self) -> io::Result<u64> {
+        self.content.length()
+    }
+
+    /// The offset of the data in the archive holding it, if read from an archive.
+    ///
+    /// The offset does not include the prefix, which is stored in the index.
+    pub fn offset(&self) -> Option<u64> {
+        match self.content {
+            Content::Record(record) | Content::ArchiveRecord { record, .. } => Some(record.start),
+            _ => None,
+        }
+    }
+
+    /// The path of the other archive file the data is read from, if any.
+    pub fn archive(&self) -> Option<&'a Path> {
+        match self.content {
+            Content::ArchiveRecord { archive, .. } => Some(archive),
+            _ => None,
+        }
+    }
+
+    /// Open a reader of the data, reading records of the archive from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the errors of [`Content::open`].
+    pub fn open<'r, R>(&self, reader: &'r mut R) -> io::Result<Box<dyn Read + 'r>>
+    where
+        'a: 'r,
+        R: Seek + Read,
+    {
+        self.content.open(reader)
+    }
+}
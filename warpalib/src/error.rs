@@ -2,7 +2,7 @@ use std::{io, path::PathBuf};
 
 use thiserror::Error;
 
-use crate::RpaVersion;
+use crate::{ArchivePath, RpaVersion, UnsafeReason};
 
 /// Type alias for a result with an `RpaError`.
 pub type RpaResult<T> = Result<T, RpaError>;
@@ -33,7 +33,43 @@ pub enum RpaError {
 
     /// File not found in dexes or content.
     #[error("file not found in indexes or content: '{0}'")]
-    NotFound(PathBuf),
+    NotFound(ArchivePath),
+
+    /// Path present in more than one source while merging.
+    #[error("duplicate path while merging: '{0}'")]
+    DuplicatePath(ArchivePath),
+
+    /// Content does not fit into a single volume while splitting.
+    #[error("content is larger than the volume size ({1} bytes): '{0}'")]
+    VolumeExceeded(ArchivePath, u64),
+
+    /// Index entry points outside of the data section of the archive.
+    #[error("index entry points outside of the data section ({1}..{2}): '{0}'")]
+    OutOfBounds(String, u64, u64),
+
+    /// Content length differs from the length resolved before writing it.
+    #[error("content length changed while writing '{0}': expected {1} bytes, got {2}")]
+    LengthMismatch(ArchivePath, u64, u64),
+
+    /// Archive content is read from the file the archive is saved to.
+    #[error("refusing to save archive over a file it reads content from: '{0}'")]
+    ReadsTarget(PathBuf),
+
+    /// Archive is locked by another process.
+    #[error("archive is locked by another process: '{0}'")]
+    Locked(PathBuf),
+
+    /// Operation stopped by a cancellation token.
+    #[error("operation cancelled")]
+    Cancelled,
+
+    /// Archive path could escape the directory it is extracted to.
+    #[error("unsafe archive path '{0}': {1}")]
+    UnsafePath(PathBuf, UnsafeReason),
+
+    /// Filesystem path used as an archive path is not valid UTF-8.
+    #[error("path is not valid UTF-8: '{0}'")]
+    NonUtf8Path(PathBuf),
 
     /// Creating archive not supported for a specific version.
     #[error("writing archive not supported for {0}")]
@@ -0,0 +1,841 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use log::{debug, trace};
+
+use crate::{
+    copy::copy_record, temp::TempFile, validate_target, ArchivePath, Content, ContentMap, Hooks,
+    RenpyArchive, RpaError,
+};
+
+/// Number of chunks of entries extracted by each thread.
+const CHUNKS_PER_THREAD: usize = 4;
+
+/// Defines what happens when an extracted file already exists.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// The existing file is replaced.
+    #[default]
+    Overwrite,
+
+    /// The existing file is kept and the entry is skipped.
+    Skip,
+
+    /// The entry fails to extract.
+    Error,
+
+    /// The existing file is only replaced if its data differs.
+    Different,
+
+    /// The entry is extracted next to the existing file with a numbered suffix.
+    Rename,
+}
+
+/// A filter selecting the content to extract.
+type FilterFn<'a> = dyn Fn(&ArchivePath, &Content) -> bool + Sync + 'a;
+
+/// A callback receiving the progress of an extraction.
+type ProgressFn<'a> = dyn Fn(&ExtractProgress) + Sync + 'a;
+
+/// Options for extracting an archive with [`RenpyArchive::extract_to`].
+///
+/// # Examples
+///
+/// ```rust
+/// use warpalib::{ExtractOptions, OverwritePolicy};
+///
+/// let options = ExtractOptions::new()
+///     .with_filter(|path, _| path.as_str().ends_with(".rpy"))
+///     .with_overwrite(OverwritePolicy::Skip)
+///     .with_progress(|progress| println!("{}", progress.target.display()));
+/// ```
+pub struct ExtractOptions<'a> {
+    filter: Option<Box<FilterFn<'a>>>,
+    overwrite: OverwritePolicy,
+    allow_unsafe: bool,
+    threads: usize,
+    progress: Option<Box<ProgressFn<'a>>>,
+    hooks: Hooks,
+}
+
+impl Default for ExtractOptions<'_> {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            overwrite: OverwritePolicy::default(),
+            allow_unsafe: false,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            progress: None,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+impl<'a> ExtractOptions<'a> {
+    /// Create options extracting all content, overwriting existing files and
+    /// rejecting unsafe paths.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only extract the content for which `filter` returns true.
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ArchivePath, &Content) -> bool + Sync + 'a,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Set what happens when an extracted file already exists.
+    pub fn with_overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Extract paths that could escape the output directory instead of failing them.
+    ///
+    /// See [`validate_path`](crate::validate_path) and [`validate_target`] for the paths
+    /// considered unsafe.
+    pub fn with_allow_unsafe(mut self, allow_unsafe: bool) -> Self {
+        self.allow_unsafe = allow_unsafe;
+        self
+    }
+
+    /// Set the number of threads extracting content. Defaults to the available
+    /// parallelism.
+    ///
+    /// This only applies to archives that can be read concurrently.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Call `progress` after each entry is handled.
+    ///
+    /// The callback may be called from multiple threads at once.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&ExtractProgress) + Sync + 'a,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Report the bytes copied to and check cancellation of the `hooks`.
+    ///
+    /// Once cancelled, threads stop after removing the file they were writing. Files
+    /// being overwritten are only replaced once their new data is complete, so they are
+    /// kept as they were.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+}
+
+/// The progress of an extraction, reported after each entry.
+#[derive(Debug)]
+pub struct ExtractProgress<'e> {
+    /// The path of the entry in the archive.
+    pub path: &'e ArchivePath,
+
+    /// The file the entry was extracted to.
+    pub target: &'e Path,
+
+    /// What happened to the entry.
+    pub outcome: ExtractOutcome<'e>,
+}
+
+/// What happened to an extracted entry.
+#[derive(Debug)]
+pub enum ExtractOutcome<'e> {
+    /// The entry was written to its target.
+    Written,
+
+    /// The entry was skipped as its target exists.
+    Skipped,
+
+    /// The entry failed to extract.
+    Failed(&'e RpaError),
+}
+
+/// The entries handled by an extraction.
+#[derive(Debug, Default)]
+pub struct ExtractSummary {
+    /// The files written.
+    pub written: Vec<PathBuf>,
+
+    /// The files skipped as they exist.
+    pub skipped: Vec<PathBuf>,
+
+    /// The entries that failed to extract along with their error.
+    pub failed: Vec<(ArchivePath, RpaError)>,
+
+    /// Whether the extraction was cancelled before handling every entry.
+    pub cancelled: bool,
+}
+
+impl ExtractSummary {
+    /// Whether every entry was either written or skipped.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && !self.cancelled
+    }
+
+    fn append(&mut self, other: ExtractSummary) {
+        self.written.extend(other.written);
+        self.skipped.extend(other.skipped);
+        self.failed.extend(other.failed);
+        self.cancelled |= other.cancelled;
+    }
+}
+
+impl<R> RenpyArchive<R>
+where
+    R: Seek + BufRead,
+{
+    /// Extract the content of the archive into `dir`, reading records in the order
+    /// they are stored.
+    ///
+    /// Entries failing to extract do not stop the extraction and are reported in the
+    /// returned summary instead. The archive has a single reader, so content is
+    /// extracted on the calling thread. Use [`SharedArchive`](crate::SharedArchive)
+    /// to extract on multiple threads.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{ExtractOptions, RenpyArchive};
+    ///
+    /// let dir = std::env::temp_dir().join("warpalib-extract-example");
+    ///
+    /// let mut archive = RenpyArchive::new();
+    /// archive.content.insert_raw("script.rpy", b"label start:".to_vec());
+    /// archive.content.insert_raw("../escape.rpy", vec![]);
+    ///
+    /// let summary = archive.extract_to(&dir, &ExtractOptions::new());
+    /// assert_eq!(summary.written, [dir.join("script.rpy")]);
+    /// assert_eq!(summary.failed.len(), 1);
+    /// # std::fs::remove_dir_all(dir).unwrap();
+    /// ```
+    pub fn extract_to(&mut self, dir: &Path, options: &ExtractOptions) -> ExtractSummary {
+        let entries = select(&self.content, options);
+        extract_chunk(&mut self.reader, None, dir, &entries, options)
+    }
+}
+
+/// The content of `map` selected by `options` in the order it is stored, reported
+/// as started to the hooks.
+pub(crate) fn select<'m>(
+    map: &'m ContentMap,
+    options: &ExtractOptions,
+) -> Vec<(&'m ArchivePath, &'m Content)> {
+    let mut entries = map.sorted_by_offset();
+    if let Some(filter) = options.filter.as_ref() {
+        entries.retain(|(path, content)| filter(path, content));
+    }
+    options
+        .hooks
+        .started(entries.iter().map(|(_, content)| *content));
+    entries
+}
+
+/// Extract the `entries` into `dir` using multiple threads, each with a reader
+/// created by `init`.
+///
+/// Entries are split into contiguous chunks, so each thread reads its part of the
+/// archive sequentially. Records are copied straight from the archive file `source`
+/// if given, see [`extract_chunk`].
+pub(crate) fn extract_parallel<R, F>(
+    init: F,
+    source: Option<&File>,
+    dir: &Path,
+    entries: &[(&ArchivePath, &Content)],
+    options: &ExtractOptions,
+) -> ExtractSummary
+where
+    R: Read + Seek,
+    F: Fn() -> R + Sync,
+{
+    if options.threads <= 1 || entries.len() <= 1 {
+        return extract_chunk(&mut init(), source, dir, entries, options);
+    }
+
+    // Smaller chunks than threads keep them busy when chunks take uneven time.
+    let chunk_size = entries
+        .len()
+        .div_ceil(options.threads * CHUNKS_PER_THREAD)
+        .max(1);
+    let chunks = entries.chunks(chunk_size).collect::<Vec<_>>();
+    debug!(
+        "Extracting {} entries in {} chunks on {} threads",
+        entries.len(),
+        chunks.len(),
+        options.threads
+    );
+
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut summaries = thread::scope(|scope| {
+        let workers = (0..options.threads.min(chunks.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut reader = init();
+                    let mut summaries = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some(chunk) = chunks.get(index) else {
+                            return summaries;
+                        };
+                        let summary = extract_chunk(&mut reader, source, dir, chunk, options);
+                        summaries.push((index, summary));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Extraction thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    // Report in the order of the entries regardless of the thread handling them.
+    summaries.sort_by_key(|(index, _)| *index);
+    let mut summary = ExtractSummary::default();
+    for (_, chunk) in summaries {
+        summary.append(chunk);
+    }
+    summary
+}
+
+/// Extract the `entries` into `dir` one after another.
+///
+/// Records are copied from the archive file `source` with explicit offsets if given,
+/// which copies them within the kernel on Linux. Other content is read from `reader`.
+fn extract_chunk<R: Read + Seek>(
+    reader: &mut R,
+    source: Option<&File>,
+    dir: &Path,
+    entries: &[(&ArchivePath, &Content)],
+    options: &ExtractOptions,
+) -> ExtractSummary {
+    let mut summary = ExtractSummary::default();
+
+    for (path, content) in entries {
+        let mut target = dir.join(path.to_path());
+        let result = match options.hooks.check() {
+            Ok(()) => extract_entry(reader, source, dir, path, content, &mut target, options),
+            Err(e) => Err(e),
+        };
+        if let Err(RpaError::Cancelled) = result {
+            summary.cancelled = true;
+            break;
+        }
+
+        let outcome = match &result {
+            Ok(true) => ExtractOutcome::Written,
+            Ok(false) => ExtractOutcome::Skipped,
+            Err(e) => ExtractOutcome::Failed(e),
+        };
+        if let Some(progress) = options.progress.as_ref() {
+            progress(&ExtractProgress {
+                path,
+                target: &target,
+                outcome,
+            });
+        }
+
+        match result {
+            Ok(true) => summary.written.push(target),
+            Ok(false) => summary.skipped.push(target),
+            Err(e) => summary.failed.push(((*path).clone(), e)),
+        }
+    }
+
+    summary
+}
+
+/// Extract a single entry to `target`, returning whether it was written.
+///
+/// The `target` is updated if the entry is renamed.
+fn extract_entry<R: Read + Seek>(
+    reader: &mut R,
+    source: Option<&File>,
+    dir: &Path,
+    path: &ArchivePath,
+    content: &Content,
+    target: &mut PathBuf,
+    options: &ExtractOptions,
+) -> Result<bool, RpaError> {
+    if !options.allow_unsafe {
+        path.validate()?;
+        validate_target(dir, &path.to_path())?;
+    }
+
+    if let Some(parent) = target.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let claim = match options.overwrite {
+        // Renamed entries claim their target before writing it, so entries extracted
+        // in parallel never pick the same name. The claim is removed on errors.
+        OverwritePolicy::Rename => Some(claim_path(target)?),
+        _ if !target.exists() => None,
+        OverwritePolicy::Overwrite => None,
+        OverwritePolicy::Skip => {
+            debug!("Skipping existing {}", target.display());
+            return Ok(false);
+        }
+        OverwritePolicy::Error => {
+            return Err(RpaError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            )))
+        }
+        OverwritePolicy::Different => {
+            let mut compare = CompareWriter::open(target)?;
+            content.copy_to(reader, &mut compare)?;
+            if compare.finish()? {
+                debug!("Skipping unchanged {}", target.display());
+                return Ok(false);
+            }
+            None
+        }
+    };
+
+    trace!("Extracting {path} to {}", target.display());
+
+    // Write next to the target and replace it once complete, so a failed or cancelled
+    // copy keeps the existing file. The temp file is removed on errors.
+    let (temp, mut file) = TempFile::create(target)?;
+    match (content, source) {
+        (Content::Record(record), Some(source)) => options.hooks.copy_with(path, |between| {
+            copy_record(source, record, &mut file, between)
+        })?,
+        _ => options.hooks.copy(path, content, reader, &mut file)?,
+    };
+
+    if claim.is_none() {
+        if let Ok(metadata) = fs::metadata(&*target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+    }
+    drop(file);
+    temp.persist(target)?;
+    if let Some(claim) = claim {
+        claim.keep();
+    }
+    Ok(true)
+}
+
+/// Create an empty file at `target`, or at the next free path if it exists, updating
+/// `target` to the path claimed.
+fn claim_path(target: &mut PathBuf) -> io::Result<TempFile> {
+    let path = target.clone();
+    loop {
+        match TempFile::create_new(target) {
+            Ok(claim) => return Ok(claim),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                *target = free_path(&path, |_| false);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Find a path that does not exist and is not `taken` by adding a numbered suffix to
+/// the file stem of `path`, as done by [`OverwritePolicy::Rename`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::Path;
+/// use warpalib::free_path;
+///
+/// let dir = std::env::temp_dir().join("warpalib-free-path-example");
+/// let path = free_path(&dir.join("bg.png"), |p| p.ends_with("bg_1.png"));
+/// assert_eq!(path, dir.join("bg_2.png"));
+/// ```
+pub fn free_path<F: Fn(&Path) -> bool>(path: &Path, taken: F) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|e| e.to_string_lossy());
+
+    (1..)
+        .map(|n| match extension.as_ref() {
+            Some(extension) => path.with_file_name(format!("{stem}_{n}.{extension}")),
+            None => path.with_file_name(format!("{stem}_{n}")),
+        })
+        .find(|p| !p.exists() && !taken(p))
+        .expect("Ran out of suffixes")
+}
+
+/// A writer comparing the written data with an existing file.
+struct CompareWriter {
+    file: BufReader<File>,
+    equal: bool,
+}
+
+impl CompareWriter {
+    fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufReader::new(File::open(path)?),
+            equal: true,
+        })
+    }
+
+    /// Whether the written data is equal to the whole file.
+    fn finish(mut self) -> io::Result<bool> {
+        Ok(self.equal && self.file.read(&mut [0u8])? == 0)
+    }
+}
+
+impl Write for CompareWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.equal {
+            let mut existing = vec![0u8; buf.len()];
+            match self.file.read_exact(&mut existing) {
+                Ok(()) => self.equal = existing == buf,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.equal = false,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{copy::CHUNK, CancelToken, ProgressObserver, SharedArchive, UnsafeReason};
+
+    use super::*;
+
+    /// Create an empty directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("warpalib-extract-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Cancels its token once any bytes are copied.
+    struct CancelOnCopy(CancelToken);
+
+    impl ProgressObserver for CancelOnCopy {
+        fn bytes_copied(&self, _bytes: u64) {
+            self.0.cancel();
+        }
+    }
+
+    fn cancelling_options() -> ExtractOptions<'static> {
+        let token = CancelToken::new();
+        let hooks = Hooks::new()
+            .with_observer(Arc::new(CancelOnCopy(token.clone())))
+            .with_cancel(token);
+        ExtractOptions::new().with_hooks(hooks)
+    }
+
+    #[test]
+    fn cancelled_overwrite_should_keep_existing_file() {
+        let dir = test_dir("cancel");
+        let path = dir.join("video.rpa");
+        let mut archive = RenpyArchive::new();
+        archive
+            .content
+            .insert_raw("video.webm", vec![7u8; CHUNK as usize * 2]);
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("video.webm"), b"old").unwrap();
+
+        let summary = SharedArchive::open(&path)
+            .unwrap()
+            .extract_to(&out, &cancelling_options());
+        assert!(summary.cancelled);
+
+        let summary = RenpyArchive::open(&path)
+            .unwrap()
+            .extract_to(&out, &cancelling_options());
+        assert!(summary.cancelled);
+
+        // Neither the existing file nor temp files are left changed.
+        let files = fs::read_dir(&out).unwrap().count();
+        assert_eq!(files, 1);
+        assert_eq!(fs::read(out.join("video.webm")).unwrap(), b"old");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled_rename_should_remove_claimed_path() {
+        let dir = test_dir("cancel-rename");
+        let path = dir.join("video.rpa");
+        let mut archive = RenpyArchive::new();
+        archive
+            .content
+            .insert_raw("video.webm", vec![7u8; CHUNK as usize * 2]);
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("video.webm"), b"old").unwrap();
+
+        let options = cancelling_options().with_overwrite(OverwritePolicy::Rename);
+        let summary = SharedArchive::open(&path)
+            .unwrap()
+            .extract_to(&out, &options);
+        assert!(summary.cancelled);
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parallel_rename_should_keep_colliding_entries() {
+        let dir = test_dir("parallel-rename");
+        let path = dir.join("images.rpa");
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+
+        // Renaming each existing `bgN.png` collides with the next entry `bgN_1.png`,
+        // which is extracted at the same time on another thread.
+        let pairs = 8;
+        let mut archive = RenpyArchive::new();
+        let mut expected = vec![];
+        for n in 0..pairs {
+            fs::write(out.join(format!("bg{n}.png")), b"existing").unwrap();
+            expected.push(b"existing".to_vec());
+
+            for name in [format!("bg{n}.png"), format!("bg{n}_1.png")] {
+                let mut data = vec![0u8; 1 << 20];
+                data[..name.len()].copy_from_slice(name.as_bytes());
+                archive.content.insert_raw(name, data.clone());
+                expected.push(data);
+            }
+        }
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+
+        // A chunk and a thread for each entry.
+        let options = ExtractOptions::new()
+            .with_overwrite(OverwritePolicy::Rename)
+            .with_threads(pairs * 2);
+        let summary = SharedArchive::open(&path)
+            .unwrap()
+            .extract_to(&out, &options);
+        assert!(summary.is_success());
+        assert_eq!(summary.written.len(), pairs * 2);
+
+        let mut found = fs::read_dir(&out)
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect::<Vec<_>>();
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// An archive with `script.rpy` and `images/bg.png`.
+    fn archive() -> RenpyArchive<io::Cursor<Vec<u8>>> {
+        let mut archive = RenpyArchive::new();
+        archive
+            .content
+            .insert_raw("script.rpy", b"label start:".to_vec());
+        archive.content.insert_raw("images/bg.png", b"png".to_vec());
+        archive
+    }
+
+    /// Extract with `policy` over an existing `script.rpy`.
+    fn extract_over(
+        name: &str,
+        existing: &[u8],
+        policy: OverwritePolicy,
+    ) -> (PathBuf, ExtractSummary) {
+        let dir = test_dir(name);
+        fs::write(dir.join("script.rpy"), existing).unwrap();
+
+        let options = ExtractOptions::new()
+            .with_overwrite(policy)
+            .with_filter(|path, _| path.as_str() == "script.rpy");
+        let summary = archive().extract_to(&dir, &options);
+        (dir, summary)
+    }
+
+    #[test]
+    fn should_skip_existing_files() {
+        let (dir, summary) = extract_over("skip", b"old", OverwritePolicy::Skip);
+        assert_eq!(summary.skipped, [dir.join("script.rpy")]);
+        assert!(summary.written.is_empty() && summary.is_success());
+        assert_eq!(fs::read(dir.join("script.rpy")).unwrap(), b"old");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_fail_existing_files() {
+        let (dir, summary) = extract_over("error", b"old", OverwritePolicy::Error);
+        assert!(!summary.is_success());
+        assert!(matches!(
+            &summary.failed[..],
+            [(path, RpaError::Io(e))]
+                if path.as_str() == "script.rpy" && e.kind() == io::ErrorKind::AlreadyExists
+        ));
+        assert_eq!(fs::read(dir.join("script.rpy")).unwrap(), b"old");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_only_replace_different_files() {
+        let (dir, summary) = extract_over("same", b"label start:", OverwritePolicy::Different);
+        assert_eq!(summary.skipped, [dir.join("script.rpy")]);
+        fs::remove_dir_all(dir).unwrap();
+
+        // A prefix of the new data is different as well.
+        for existing in [&b"label"[..], b"label start: ", b"old"] {
+            let (dir, summary) = extract_over("different", existing, OverwritePolicy::Different);
+            assert_eq!(summary.written, [dir.join("script.rpy")]);
+            assert_eq!(fs::read(dir.join("script.rpy")).unwrap(), b"label start:");
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_rename_next_to_existing_files() {
+        let (dir, summary) = extract_over("rename", b"old", OverwritePolicy::Rename);
+        assert_eq!(summary.written, [dir.join("script_1.rpy")]);
+        assert_eq!(fs::read(dir.join("script.rpy")).unwrap(), b"old");
+        assert_eq!(fs::read(dir.join("script_1.rpy")).unwrap(), b"label start:");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_unsafe_paths() {
+        let dir = test_dir("unsafe");
+        let mut archive = archive();
+        archive.content.insert_raw("../escape.rpy", vec![]);
+        archive.content.insert_raw("/escape.rpy", vec![]);
+
+        let summary = archive.extract_to(&dir.join("out"), &ExtractOptions::new());
+        let mut reasons = summary
+            .failed
+            .iter()
+            .map(|(path, e)| match e {
+                RpaError::UnsafePath(_, reason) => (path.to_string(), *reason),
+                e => panic!("unexpected error {e}"),
+            })
+            .collect::<Vec<_>>();
+        reasons.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            reasons,
+            [
+                ("../escape.rpy".to_string(), UnsafeReason::ParentDir),
+                ("/escape.rpy".to_string(), UnsafeReason::Absolute),
+            ]
+        );
+        assert_eq!(summary.written.len(), 2);
+        assert!(!dir.join("escape.rpy").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_reject_paths_through_links() {
+        let dir = test_dir("links");
+        let outside = test_dir("links-outside");
+        std::os::unix::fs::symlink(&outside, dir.join("images")).unwrap();
+
+        let summary = archive().extract_to(&dir, &ExtractOptions::new());
+        assert!(matches!(
+            &summary.failed[..],
+            [(_, RpaError::UnsafePath(_, UnsafeReason::Symlink))]
+        ));
+        assert!(!outside.join("bg.png").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[test]
+    fn summary_should_list_every_entry() {
+        let dir = test_dir("summary");
+        fs::write(dir.join("script.rpy"), b"old").unwrap();
+        let mut archive = archive();
+        archive.content.insert_raw("../escape.rpy", vec![]);
+
+        let outcomes = Mutex::new(Vec::new());
+        let options = ExtractOptions::new()
+            .with_overwrite(OverwritePolicy::Skip)
+            .with_progress(|progress| {
+                let outcome = match progress.outcome {
+                    ExtractOutcome::Written => "written",
+                    ExtractOutcome::Skipped => "skipped",
+                    ExtractOutcome::Failed(_) => "failed",
+                };
+                outcomes
+                    .lock()
+                    .unwrap()
+                    .push((progress.path.to_string(), outcome));
+            });
+        let summary = archive.extract_to(&dir, &options);
+
+        assert_eq!(summary.written, [dir.join("images/bg.png")]);
+        assert_eq!(summary.skipped, [dir.join("script.rpy")]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0.as_str(), "../escape.rpy");
+        assert!(!summary.cancelled && !summary.is_success());
+
+        drop(options);
+        let mut outcomes = outcomes.into_inner().unwrap();
+        outcomes.sort();
+        assert_eq!(
+            outcomes,
+            [
+                ("../escape.rpy".to_string(), "failed"),
+                ("images/bg.png".to_string(), "written"),
+                ("script.rpy".to_string(), "skipped"),
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn free_path_should_skip_taken_paths() {
+        let dir = test_dir("free");
+        fs::write(dir.join("bg.png"), b"").unwrap();
+        fs::write(dir.join("bg_1.png"), b"").unwrap();
+        fs::write(dir.join("readme"), b"").unwrap();
+
+        assert_eq!(
+            free_path(&dir.join("bg.png"), |_| false),
+            dir.join("bg_2.png")
+        );
+        assert_eq!(
+            free_path(&dir.join("bg.png"), |p| p == dir.join("bg_2.png")),
+            dir.join("bg_3.png")
+        );
+        assert_eq!(
+            free_path(&dir.join("readme"), |_| false),
+            dir.join("readme_1")
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
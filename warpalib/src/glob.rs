@@ -1,7 +1,7 @@
-use glob::{Pattern, PatternError};
-use std::{path::PathBuf, str::FromStr};
+use glob::{MatchOptions, Pattern, PatternError};
+use std::{collections::HashMap, str::FromStr};
 
-use crate::{Content, ContentMap};
+use crate::{ArchivePath, Content, ContentMap};
 
 impl ContentMap {
     /// Return an iterator that produces all the contents in the archive
@@ -14,7 +14,6 @@ impl ContentMap {
     /// # Examples
     ///
     /// ```rust
-    /// use std::path::Path;
     /// use warpalib::RenpyArchive;
     ///
     /// // Create a new archive and add sample files.
@@ -27,21 +26,21 @@ impl ContentMap {
     /// let paths = archive.content
     ///     .glob("*.png")
     ///     .expect("Failed to compile pattern")
-    ///     .map(|(path, _)| path.as_ref())
+    ///     .map(|(path, _)| path.as_str())
     ///     .collect::<Vec<_>>();
     ///
-    /// assert!(paths.contains(&Path::new("silk.png")));
-    /// assert!(paths.contains(&Path::new("cherry.png")));
+    /// assert!(paths.contains(&"silk.png"));
+    /// assert!(paths.contains(&"cherry.png"));
     /// ```
     pub fn glob(
         &self,
         pattern: &str,
-    ) -> Result<impl Iterator<Item = (&PathBuf, &Content)>, PatternError> {
+    ) -> Result<impl Iterator<Item = (&ArchivePath, &Content)>, PatternError> {
         let pattern = Pattern::from_str(pattern)?;
 
         let iter = self
             .iter()
-            .filter(move |(path, _)| pattern.matches_path(path));
+            .filter(move |(path, _)| pattern.matches(path.as_str()));
 
         Ok(iter)
     }
@@ -51,13 +50,173 @@ impl ContentMap {
     pub fn into_glob(
         self,
         pattern: &str,
-    ) -> Result<impl Iterator<Item = (PathBuf, Content)>, PatternError> {
+    ) -> Result<impl Iterator<Item = (ArchivePath, Content)>, PatternError> {
         let pattern = Pattern::from_str(pattern)?;
 
         let iter = self
             .into_iter()
-            .filter(move |(path, _)| pattern.matches_path(path));
+            .filter(move |(path, _)| pattern.matches(path.as_str()));
 
         Ok(iter)
     }
+
+    /// Return an iterator over the contents matching any of the `patterns`
+    /// using the given match `options`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use glob::{MatchOptions, Pattern};
+    /// use warpalib::ContentMap;
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("Images/BG.png", vec![]);
+    /// map.insert_raw("audio/theme.ogg", vec![]);
+    /// map.insert_raw("script.rpy", vec![]);
+    ///
+    /// let patterns = [Pattern::new("images/*").unwrap(), Pattern::new("*.ogg").unwrap()];
+    /// let options = MatchOptions {
+    ///     case_sensitive: false,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(map.glob_with(&patterns, options).count(), 2);
+    /// ```
+    pub fn glob_with<'a>(
+        &'a self,
+        patterns: &'a [Pattern],
+        options: MatchOptions,
+    ) -> impl Iterator<Item = (&'a ArchivePath, &'a Content)> {
+        self.iter()
+            .filter(move |(path, _)| matches_any(patterns, options, path))
+    }
+
+    /// Consumes the content map and returns an iterator with owned contents
+    /// matching any of the `patterns` using the given match `options`.
+    pub fn into_glob_with(
+        self,
+        patterns: &[Pattern],
+        options: MatchOptions,
+    ) -> impl Iterator<Item = (ArchivePath, Content)> + '_ {
+        self.into_iter()
+            .filter(move |(path, _)| matches_any(patterns, options, path))
+    }
+
+    /// Keep only the contents that match the given glob pattern.
+    ///
+    /// # Errors
+    ///
+    /// This may return an error if the pattern is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::ContentMap;
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("images/bg.png", vec![]);
+    /// map.insert_raw("script.rpy", vec![]);
+    ///
+    /// map.retain_glob("images/*").expect("Failed to compile pattern");
+    ///
+    /// assert!(map.contains_key("images/bg.png"));
+    /// assert!(!map.contains_key("script.rpy"));
+    /// ```
+    pub fn retain_glob(&mut self, pattern: &str) -> Result<(), PatternError> {
+        let pattern = Pattern::from_str(pattern)?;
+        self.retain_glob_with(&[pattern], MatchOptions::new());
+        Ok(())
+    }
+
+    /// Keep only the contents that match any of the `patterns` using the given
+    /// match `options`.
+    pub fn retain_glob_with(&mut self, patterns: &[Pattern], options: MatchOptions) {
+        self.retain(|path, _| matches_any(patterns, options, path));
+    }
+
+    /// Remove the contents that match the given glob pattern and return them.
+    ///
+    /// # Errors
+    ///
+    /// This may return an error if the pattern is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::ContentMap;
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("images/bg.png", vec![]);
+    /// map.insert_raw("script.rpy", vec![]);
+    ///
+    /// let removed = map.remove_glob("*.rpy").expect("Failed to compile pattern");
+    ///
+    /// assert!(removed.contains_key("script.rpy"));
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn remove_glob(&mut self, pattern: &str) -> Result<ContentMap, PatternError> {
+        let pattern = Pattern::from_str(pattern)?;
+        Ok(self.remove_glob_with(&[pattern], MatchOptions::new()))
+    }
+
+    /// Remove the contents that match any of the `patterns` using the given
+    /// match `options` and return them.
+    pub fn remove_glob_with(&mut self, patterns: &[Pattern], options: MatchOptions) -> ContentMap {
+        let paths = self
+            .keys()
+            .filter(|path| matches_any(patterns, options, path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        paths
+            .into_iter()
+            .filter_map(|path| self.remove(&path).map(|content| (path, content)))
+            .collect::<HashMap<_, _>>()
+            .into()
+    }
+
+    /// Partition the content into one volume per pattern, followed by a volume
+    /// of content matching none of the patterns.
+    ///
+    /// Content is placed in the volume of the first pattern it matches. Volumes
+    /// may be empty.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use glob::Pattern;
+    /// use warpalib::ContentMap;
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("images/bg.png", vec![]);
+    /// map.insert_raw("script.rpy", vec![]);
+    ///
+    /// let patterns = [Pattern::new("images/*").unwrap()];
+    /// let volumes = map.split_by_patterns(&patterns);
+    ///
+    /// assert_eq!(volumes.len(), 2);
+    /// assert_eq!(volumes[0].len(), 1);
+    /// assert_eq!(volumes[1].len(), 1);
+    /// ```
+    pub fn split_by_patterns(self, patterns: &[Pattern]) -> Vec<ContentMap> {
+        let mut volumes = (0..=patterns.len())
+            .map(|_| HashMap::new())
+            .collect::<Vec<_>>();
+
+        for (path, content) in self.into_iter() {
+            let index = patterns
+                .iter()
+                .position(|pattern| pattern.matches(path.as_str()))
+                .unwrap_or(patterns.len());
+            volumes[index].insert(path, content);
+        }
+
+        volumes.into_iter().map(ContentMap::from).collect()
+    }
+}
+
+fn matches_any(patterns: &[Pattern], options: MatchOptions, path: &ArchivePath) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| pattern.matches_with(path.as_str(), options))
 }
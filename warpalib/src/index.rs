@@ -0,0 +1,748 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read},
+    rc::Rc,
+};
+
+use flate2::bufread::ZlibDecoder;
+use log::{debug, trace, warn};
+
+use crate::{record::Record, ArchivePath, Content, ContentMap, RpaError};
+
+/// Errors raised while decoding an index with [`decode_index`].
+#[derive(Debug)]
+pub(crate) enum IndexError {
+    /// The pickle uses an opcode or structure the streaming decoder does not handle.
+    Unsupported(u8),
+
+    /// The index is invalid or could not be read.
+    Rpa(RpaError),
+}
+
+impl From<io::Error> for IndexError {
+    fn from(e: io::Error) -> Self {
+        IndexError::Rpa(RpaError::Io(e))
+    }
+}
+
+type IndexResult<T> = Result<T, IndexError>;
+
+/// Decode the index from the `reader` positioned at its start.
+///
+/// The index is inflated and unpickled while it is read, without buffering the compressed
+/// or inflated bytes. Each dict entry is stored in a compact [`IndexTable`] as soon as it is
+/// complete and the pickle objects it was built from are released, so the whole index is
+/// never held as pickle values. The map of paths to records is only built once the
+/// returned content is first accessed.
+///
+/// Only the subset of pickle used by archive indexes is understood. Anything else returns
+/// [`IndexError::Unsupported`] so callers can fall back to a general decoder.
+pub(crate) fn decode_index<R: BufRead>(reader: R, key: Option<u64>) -> IndexResult<ContentMap> {
+    let mut machine = Machine {
+        reader: BufReader::new(ZlibDecoder::new(reader)),
+        key,
+        stack: Vec::new(),
+        marks: Vec::new(),
+        memo: HashMap::new(),
+        next_memo: 0,
+        table: None,
+    };
+
+    let table = machine.run()?;
+    debug!("Decoded {} index entries", table.rows.len());
+
+    Ok(ContentMap::from_index(table))
+}
+
+/// Decoded index entries with all paths stored in a single buffer, turned into a map of
+/// records by [`IndexTable::into_map`].
+#[derive(Debug, Default)]
+pub(crate) struct IndexTable {
+    /// The raw paths of every entry, one after another.
+    paths: String,
+    rows: Vec<IndexRow>,
+}
+
+/// A deobfuscated entry of an [`IndexTable`].
+#[derive(Debug)]
+struct IndexRow {
+    /// The end of the path in the paths of the table, starting at the end of the previous one.
+    path_end: usize,
+    start: u64,
+    length: u64,
+    prefix: Option<Box<[u8]>>,
+}
+
+impl IndexTable {
+    fn push(&mut self, path: &str, record: Record) {
+        self.paths.push_str(path);
+        self.rows.push(IndexRow {
+            path_end: self.paths.len(),
+            start: record.start,
+            length: record.length,
+            prefix: record.prefix.map(Vec::into_boxed_slice),
+        });
+    }
+
+    /// Build the map of normalized paths to the records of the entries.
+    pub(crate) fn into_map(self) -> HashMap<ArchivePath, Content> {
+        trace!("Building content from {} index entries", self.rows.len());
+
+        let mut content = HashMap::with_capacity(self.rows.len());
+        let mut path_start = 0;
+        for row in self.rows {
+            let path = ArchivePath::from(self.paths[path_start..row.path_end].to_owned());
+            path_start = row.path_end;
+
+            let prefix = row.prefix.map(Vec::from);
+            let record = Record::new(row.start, row.length, prefix, None);
+            if content
+                .insert(path.clone(), Content::Record(record))
+                .is_some()
+            {
+                warn!("Index has multiple paths normalized to ({path}), only one is kept");
+            }
+        }
+        content
+    }
+}
+
+/// A global callable supported in `REDUCE`.
+#[derive(Clone, Copy)]
+enum Global {
+    /// `_codecs.encode`, used by python 3 to pickle bytes with protocol 2.
+    Encode,
+
+    /// `bytes`, used by python 3 to pickle empty bytes with protocol 2.
+    Bytes,
+}
+
+/// A pickle object on the stack or in the memo.
+///
+/// Objects that are released after their entry is materialized keep their memo id.
+#[derive(Clone)]
+enum Item {
+    None,
+    Bool,
+    Int(i128),
+    Bytes(Rc<[u8]>),
+    Str(Rc<String>, Option<u32>),
+    List(Vec<Item>, Option<u32>),
+    Tuple(Rc<[Item]>, Option<u32>),
+    Global(Global),
+
+    /// The index dict. Its entries are stored as content.
+    Dict,
+
+    /// A memoized mutable object which can not be referenced again.
+    Shared,
+}
+
+struct Machine<R: Read> {
+    reader: BufReader<R>,
+    key: Option<u64>,
+    stack: Vec<Item>,
+    marks: Vec<usize>,
+    memo: HashMap<u32, Item>,
+    next_memo: u32,
+    table: Option<IndexTable>,
+}
+
+impl<R: Read> Machine<R> {
+    fn run(&mut self) -> IndexResult<IndexTable> {
+        loop {
+            let op = self.read_u8()?;
+            match op {
+                // PROTO
+                0x80 => {
+                    let protocol = self.read_u8()?;
+                    trace!("Decoding index pickle protocol {protocol}");
+                }
+                // FRAME
+                0x95 => {
+                    self.read_array::<8>()?;
+                }
+                // STOP
+                b'.' => break,
+                // MARK
+                b'(' => self.marks.push(self.stack.len()),
+
+                // NONE, NEWTRUE, NEWFALSE
+                b'N' => self.stack.push(Item::None),
+                0x88 => self.stack.push(Item::Bool),
+                0x89 => self.stack.push(Item::Bool),
+
+                // BININT, BININT1, BININT2
+                b'J' => {
+                    let value = i32::from_le_bytes(self.read_array()?);
+                    self.stack.push(Item::Int(value as i128));
+                }
+                b'K' => {
+                    let value = self.read_u8()?;
+                    self.stack.push(Item::Int(value as i128));
+                }
+                b'M' => {
+                    let value = u16::from_le_bytes(self.read_array()?);
+                    self.stack.push(Item::Int(value as i128));
+                }
+                // INT, LONG
+                b'I' | b'L' => {
+                    let line = self.read_line()?;
+                    let item = match line.as_str() {
+                        "00" | "01" => Item::Bool,
+                        line => line
+                            .trim_end_matches('L')
+                            .parse()
+                            .map(Item::Int)
+                            .map_err(|_| format_error())?,
+                    };
+                    self.stack.push(item);
+                }
+                // LONG1, LONG4
+                0x8a => {
+                    let length = self.read_u8()? as usize;
+                    self.push_long(op, length)?;
+                }
+                0x8b => {
+                    let length = u32::from_le_bytes(self.read_array()?) as usize;
+                    self.push_long(op, length)?;
+                }
+
+                // BINUNICODE, SHORT_BINUNICODE, BINUNICODE8
+                b'X' => {
+                    let length = u32::from_le_bytes(self.read_array()?) as u64;
+                    self.push_str(length)?;
+                }
+                0x8c => {
+                    let length = self.read_u8()? as u64;
+                    self.push_str(length)?;
+                }
+                0x8d => {
+                    let length = u64::from_le_bytes(self.read_array()?);
+                    self.push_str(length)?;
+                }
+                // BINSTRING, SHORT_BINSTRING (python 2 str), BINBYTES, SHORT_BINBYTES, BINBYTES8
+                b'T' | b'B' => {
+                    let length = u32::from_le_bytes(self.read_array()?) as u64;
+                    self.push_bytes(length)?;
+                }
+                b'U' | b'C' => {
+                    let length = self.read_u8()? as u64;
+                    self.push_bytes(length)?;
+                }
+                0x8e => {
+                    let length = u64::from_le_bytes(self.read_array()?);
+                    self.push_bytes(length)?;
+                }
+
+                // EMPTY_LIST, LIST, APPEND, APPENDS
+                b']' => self.stack.push(Item::List(Vec::new(), None)),
+                b'l' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Item::List(items, None));
+                }
+                b'a' => {
+                    let item = self.pop()?;
+                    self.top_list()?.push(item);
+                }
+                b'e' => {
+                    let items = self.pop_mark()?;
+                    self.top_list()?.extend(items);
+                }
+
+                // EMPTY_TUPLE, TUPLE, TUPLE1, TUPLE2, TUPLE3
+                b')' => self.stack.push(Item::Tuple(Rc::new([]), None)),
+                b't' => {
+                    let items = self.pop_mark()?;
+                    self.stack.push(Item::Tuple(items.into(), None));
+                }
+                0x85..=0x87 => {
+                    let count = (op - 0x84) as usize;
+                    if self.stack.len() < count {
+                        return Err(format_error());
+                    }
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Item::Tuple(items.into(), None));
+                }
+
+                // EMPTY_DICT, DICT, SETITEM, SETITEMS
+                b'}' => self.push_dict(op)?,
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    self.push_dict(op)?;
+                    self.insert_pairs(items)?;
+                }
+                b's' => {
+                    let value = self.pop()?;
+                    let path = self.pop()?;
+                    self.top_dict()?;
+                    self.insert(path, value)?;
+                }
+                b'u' => {
+                    let items = self.pop_mark()?;
+                    self.top_dict()?;
+                    self.insert_pairs(items)?;
+                }
+
+                // BINPUT, LONG_BINPUT, MEMOIZE
+                b'q' => {
+                    let id = self.read_u8()? as u32;
+                    self.memoize(id)?;
+                }
+                b'r' => {
+                    let id = u32::from_le_bytes(self.read_array()?);
+                    self.memoize(id)?;
+                }
+                0x94 => self.memoize(self.next_memo)?,
+                // PUT, GET
+                b'p' => {
+                    let id = self.read_line()?.parse().map_err(|_| format_error())?;
+                    self.memoize(id)?;
+                }
+                b'g' => {
+                    let id = self.read_line()?.parse().map_err(|_| format_error())?;
+                    self.get(op, id)?;
+                }
+                // BINGET, LONG_BINGET
+                b'h' => {
+                    let id = self.read_u8()? as u32;
+                    self.get(op, id)?;
+                }
+                b'j' => {
+                    let id = u32::from_le_bytes(self.read_array()?);
+                    self.get(op, id)?;
+                }
+
+                // GLOBAL, STACK_GLOBAL, REDUCE
+                b'c' => {
+                    let module = self.read_line()?;
+                    let name = self.read_line()?;
+                    self.push_global(op, &module, &name)?;
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    match (module, name) {
+                        (Item::Str(module, _), Item::Str(name, _)) => {
+                            self.push_global(op, &module, &name)?
+                        }
+                        _ => return Err(format_error()),
+                    }
+                }
+                b'R' => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let item = reduce(op, callable, args)?;
+                    self.stack.push(item);
+                }
+
+                _ => return Err(IndexError::Unsupported(op)),
+            }
+        }
+
+        match (self.stack.pop(), self.table.take()) {
+            (Some(Item::Dict), Some(table)) if self.stack.is_empty() => Ok(table),
+            _ => Err(format_error()),
+        }
+    }
+
+    fn read_u8(&mut self) -> IndexResult<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_array<const N: usize>(&mut self) -> IndexResult<[u8; N]> {
+        let mut buffer = [0u8; N];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn read_vec(&mut self, length: u64) -> IndexResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.reader.by_ref().take(length).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 != length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(buffer)
+    }
+
+    fn read_line(&mut self) -> IndexResult<String> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+
+    fn push_long(&mut self, op: u8, length: usize) -> IndexResult<()> {
+        if length > 16 {
+            return Err(IndexError::Unsupported(op));
+        }
+
+        let bytes = self.read_vec(length as u64)?;
+        let mut value = [0u8; 16];
+        value[..length].copy_from_slice(&bytes);
+        // Sign extend negative numbers.
+        if bytes.last().is_some_and(|b| b & 0x80 != 0) {
+            value[length..].fill(0xff);
+        }
+
+        self.stack.push(Item::Int(i128::from_le_bytes(value)));
+        Ok(())
+    }
+
+    fn push_str(&mut self, length: u64) -> IndexResult<()> {
+        let bytes = self.read_vec(length)?;
+        let value = String::from_utf8(bytes).map_err(|_| format_error())?;
+        self.stack.push(Item::Str(Rc::new(value), None));
+        Ok(())
+    }
+
+    fn push_bytes(&mut self, length: u64) -> IndexResult<()> {
+        let bytes = self.read_vec(length)?;
+        self.stack.push(Item::Bytes(bytes.into()));
+        Ok(())
+    }
+
+    fn push_dict(&mut self, op: u8) -> IndexResult<()> {
+        // Only the index itself is expected to be a dict.
+        if self.table.is_some() {
+            return Err(IndexError::Unsupported(op));
+        }
+
+        self.table = Some(IndexTable::default());
+        self.stack.push(Item::Dict);
+        Ok(())
+    }
+
+    fn push_global(&mut self, op: u8, module: &str, name: &str) -> IndexResult<()> {
+        let global = match (module, name) {
+            ("_codecs", "encode") => Global::Encode,
+            ("__builtin__" | "builtins", "bytes") => Global::Bytes,
+            _ => return Err(IndexError::Unsupported(op)),
+        };
+
+        self.stack.push(Item::Global(global));
+        Ok(())
+    }
+
+    fn pop(&mut self) -> IndexResult<Item> {
+        self.stack.pop().ok_or_else(format_error)
+    }
+
+    fn pop_mark(&mut self) -> IndexResult<Vec<Item>> {
+        match self.marks.pop() {
+            Some(mark) if mark <= self.stack.len() => Ok(self.stack.split_off(mark)),
+            _ => Err(format_error()),
+        }
+    }
+
+    fn top_list(&mut self) -> IndexResult<&mut Vec<Item>> {
+        match self.stack.last_mut() {
+            Some(Item::List(items, _)) => Ok(items),
+            _ => Err(format_error()),
+        }
+    }
+
+    fn top_dict(&self) -> IndexResult<()> {
+        match self.stack.last() {
+            Some(Item::Dict) => Ok(()),
+            _ => Err(format_error()),
+        }
+    }
+
+    fn memoize(&mut self, id: u32) -> IndexResult<()> {
+        let top = self.stack.last_mut().ok_or_else(format_error)?;
+        if let Item::Str(_, memo) | Item::List(_, memo) | Item::Tuple(_, memo) = top {
+            *memo = Some(id);
+        }
+
+        let item = match top {
+            Item::List(..) | Item::Dict => Item::Shared,
+            item => item.clone(),
+        };
+
+        self.memo.insert(id, item);
+        self.next_memo = self.next_memo.max(id + 1);
+        Ok(())
+    }
+
+    fn get(&mut self, op: u8, id: u32) -> IndexResult<()> {
+        match self.memo.get(&id) {
+            // Either never memoized, released with its entry or mutable.
+            None | Some(Item::Shared) => Err(IndexError::Unsupported(op)),
+            Some(item) => {
+                self.stack.push(item.clone());
+                Ok(())
+            }
+        }
+    }
+
+    fn release(&mut self, memo: Option<u32>) {
+        if let Some(id) = memo {
+            self.memo.remove(&id);
+        }
+    }
+
+    fn insert_pairs(&mut self, items: Vec<Item>) -> IndexResult<()> {
+        if !items.len().is_multiple_of(2) {
+            return Err(format_error());
+        }
+
+        let mut items = items.into_iter();
+        while let (Some(path), Some(value)) = (items.next(), items.next()) {
+            self.insert(path, value)?;
+        }
+        Ok(())
+    }
+
+    /// Store a dict entry in the table and release the objects it was built from.
+    fn insert(&mut self, path: Item, value: Item) -> IndexResult<()> {
+        let path = match path {
+            Item::Str(path, memo) => {
+                self.release(memo);
+                Rc::try_unwrap(path).unwrap_or_else(|path| (*path).clone())
+            }
+            Item::Bytes(path) => String::from_utf8(path.to_vec()).map_err(|_| format_error())?,
+            _ => return Err(format_error()),
+        };
+
+        let section = match value {
+            Item::List(mut items, memo) => {
+                self.release(memo);
+                items.truncate(1);
+                items.pop()
+            }
+            Item::Tuple(items, memo) => {
+                self.release(memo);
+                items.first().cloned()
+            }
+            _ => None,
+        };
+
+        let fields = match section {
+            Some(Item::Tuple(fields, memo)) => {
+                self.release(memo);
+                fields.to_vec()
+            }
+            Some(Item::List(fields, memo)) => {
+                self.release(memo);
+                fields
+            }
+            _ => return Err(format_error()),
+        };
+
+        let mut fields = fields.into_iter();
+        let record = match (fields.next(), fields.next(), fields.next()) {
+            (Some(Item::Int(start)), Some(Item::Int(length)), prefix) => {
+                let prefix = match prefix {
+                    None | Some(Item::None) => None,
+                    Some(Item::Bytes(prefix)) => Some(prefix.to_vec()),
+                    Some(Item::Str(prefix, _)) => Some(latin1(&prefix)?),
+                    Some(_) => return Err(format_error()),
+                };
+                Record::new(to_u64(start)?, to_u64(length)?, prefix, self.key)
+                    .checked()
+                    .map_err(IndexError::Rpa)?
+            }
+            _ => return Err(format_error()),
+        };
+
+        self.table
+            .as_mut()
+            .ok_or_else(format_error)?
+            .push(&path, record);
+        Ok(())
+    }
+}
+
+/// Call a supported global with `args`.
+fn reduce(op: u8, callable: Item, args: Item) -> IndexResult<Item> {
+    let args = match args {
+        Item::Tuple(args, _) => args,
+        _ => return Err(format_error()),
+    };
+
+    match (callable, &args[..]) {
+        (Item::Global(Global::Encode), [Item::Str(value, _), Item::Str(encoding, _)])
+            if matches!(encoding.as_str(), "latin1" | "latin-1") =>
+        {
+            Ok(Item::Bytes(latin1(value)?.into()))
+        }
+        (Item::Global(Global::Bytes), []) => Ok(Item::Bytes(Rc::new([]))),
+        _ => Err(IndexError::Unsupported(op)),
+    }
+}
+
+/// Encode a string as latin-1 bytes.
+fn latin1(value: &str) -> IndexResult<Vec<u8>> {
+    value
+        .chars()
+        .map(|c| u8::try_from(c).map_err(|_| format_error()))
+        .collect()
+}
+
+/// Convert a pickle integer into an index value, wrapping negative numbers.
+fn to_u64(value: i128) -> IndexResult<u64> {
+    match i64::try_from(value) {
+        Ok(value) => Ok(value as u64),
+        Err(_) => u64::try_from(value).map_err(|_| format_error()),
+    }
+}
+
+fn format_error() -> IndexError {
+    IndexError::Rpa(RpaError::FormatRecord)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use crate::{write_header, RenpyArchive, RpaVersion};
+
+    use super::*;
+
+    // Fixtures are pickled by python 3 from renpy style indexes obfuscated with `KEY`:
+    // `{"script.rpy": [(51 ^ KEY, 5 ^ KEY, b"")], "images/bg.png": [(56 ^ KEY, 7 ^ KEY, b"> ")]}`
+    const KEY: u64 = 0xDEADBEEF;
+
+    const PROTOCOL_0: &[u8] = b"(dp0\nVscript.rpy\np1\n(lp2\n(L3735928540L\nL3735928554L\nc__builtin__\nbytes\np3\n(tRp4\ntp5\nasVimages/bg.png\np6\n(lp7\n(L3735928535L\nL3735928552L\nc_codecs\nencode\np8\n(V> \np9\nVlatin1\np10\ntp11\nRp12\ntp13\nas.";
+
+    const PROTOCOL_2: &[u8] = b"\x80\x02}q\x00(X\x0a\x00\x00\x00script.rpyq\x01]q\x02\x8a\x05\xdc\xbe\xad\xde\x00\x8a\x05\xea\xbe\xad\xde\x00c__builtin__\nbytes\nq\x03)Rq\x04\x87q\x05aX\x0d\x00\x00\x00images/bg.pngq\x06]q\x07\x8a\x05\xd7\xbe\xad\xde\x00\x8a\x05\xe8\xbe\xad\xde\x00c_codecs\nencode\nq\x08X\x02\x00\x00\x00> q\x09X\x06\x00\x00\x00latin1q\x0a\x86q\x0bRq\x0c\x87q\x0dau.";
+
+    const PROTOCOL_4: &[u8] = b"\x80\x04\x95P\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x0ascript.rpy\x94]\x94\x8a\x05\xdc\xbe\xad\xde\x00\x8a\x05\xea\xbe\xad\xde\x00C\x00\x94\x87\x94a\x8c\x0dimages/bg.png\x94]\x94\x8a\x05\xd7\xbe\xad\xde\x00\x8a\x05\xe8\xbe\xad\xde\x00C\x02> \x94\x87\x94au.";
+
+    /// `{"big.bin": [(0x1_0000_0033, 0x2_0000_0000, b"")]}` with protocol 2.
+    const LONG_OFFSETS: &[u8] = b"\x80\x02}q\x00X\x07\x00\x00\x00big.binq\x01]q\x02\x8a\x053\x00\x00\x00\x01\x8a\x05\x00\x00\x00\x00\x02c__builtin__\nbytes\nq\x03)Rq\x04\x87q\x05as.";
+
+    /// `{"a.txt": [(10, 20, prefix)], "b.txt": [(30, 40, prefix)]}` with the `b"PK"` prefix
+    /// shared, so the second entry gets it from the memo.
+    const SHARED_PREFIX: &[u8] = b"\x80\x02}q\x00(X\x05\x00\x00\x00a.txtq\x01]q\x02K\x0aK\x14c_codecs\nencode\nq\x03X\x02\x00\x00\x00PKq\x04X\x06\x00\x00\x00latin1q\x05\x86q\x06Rq\x07\x87q\x08aX\x05\x00\x00\x00b.txtq\x09]q\x0aK\x1eK(h\x07\x87q\x0bau.";
+
+    /// `{"a.txt": sections, "b.txt": sections}` with the same list of sections, which the
+    /// decoder does not resolve from the memo.
+    const SHARED_LIST: &[u8] = b"\x80\x02}q\x00(X\x05\x00\x00\x00a.txtq\x01]q\x02K3K\x05c__builtin__\nbytes\nq\x03)Rq\x04\x87q\x05aX\x05\x00\x00\x00b.txtq\x06h\x02u.";
+
+    fn compress(pickle: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(pickle).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(pickle: &[u8], key: Option<u64>) -> IndexResult<ContentMap> {
+        decode_index(&compress(pickle)[..], key)
+    }
+
+    fn record(content: &ContentMap, path: &str) -> Record {
+        match content.get(path) {
+            Some(Content::Record(record)) => record.clone(),
+            _ => panic!("{path} is not a record"),
+        }
+    }
+
+    /// Assert `content` holds the renpy style index, with empty prefixes read as none.
+    fn assert_renpy_index(content: &ContentMap) {
+        let section = |path| {
+            let record = record(content, path);
+            (
+                record.start,
+                record.length,
+                record.prefix.unwrap_or_default(),
+            )
+        };
+        assert_eq!(content.len(), 2);
+        assert_eq!(section("script.rpy"), (51, 5, vec![]));
+        assert_eq!(section("images/bg.png"), (56, 7, b"> ".to_vec()));
+    }
+
+    /// An archive with `pickle` as its index and no data.
+    fn archive_with(pickle: &[u8]) -> Cursor<Vec<u8>> {
+        let mut archive = Cursor::new(Vec::new());
+        write_header(&mut archive, &RpaVersion::V3_0, 34, Some(KEY)).unwrap();
+        archive.write_all(&compress(pickle)).unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    #[test]
+    fn should_decode_protocol_2() {
+        let content = decode(PROTOCOL_2, Some(KEY)).unwrap();
+        assert_renpy_index(&content);
+    }
+
+    #[test]
+    fn should_decode_protocol_4() {
+        let content = decode(PROTOCOL_4, Some(KEY)).unwrap();
+        assert_renpy_index(&content);
+    }
+
+    #[test]
+    fn should_decode_long_offsets() {
+        let content = decode(LONG_OFFSETS, None).unwrap();
+        assert_eq!(
+            record(&content, "big.bin"),
+            Record::new(0x1_0000_0033, 0x2_0000_0000, Some(vec![]), None)
+        );
+    }
+
+    #[test]
+    fn should_get_prefix_from_memo() {
+        let content = decode(SHARED_PREFIX, None).unwrap();
+        assert_eq!(
+            record(&content, "a.txt"),
+            Record::new(10, 20, Some(b"PK".to_vec()), None)
+        );
+        assert_eq!(
+            record(&content, "b.txt"),
+            Record::new(30, 40, Some(b"PK".to_vec()), None)
+        );
+    }
+
+    #[test]
+    fn should_report_unsupported_pickles() {
+        // Protocol 0 writes strings with UNICODE.
+        assert!(matches!(
+            decode(PROTOCOL_0, Some(KEY)),
+            Err(IndexError::Unsupported(b'V'))
+        ));
+        assert!(matches!(
+            decode(SHARED_LIST, None),
+            Err(IndexError::Unsupported(b'h'))
+        ));
+    }
+
+    #[test]
+    fn should_fall_back_on_unsupported_pickles() {
+        let archive = RenpyArchive::read(archive_with(PROTOCOL_0)).unwrap();
+        assert_renpy_index(&archive.content);
+    }
+
+    #[test]
+    fn should_reject_invalid_indexes() {
+        // A dict entry without sections.
+        let pickle = b"\x80\x02}q\x00X\x05\x00\x00\x00a.txtq\x01K\x01s.";
+        assert!(matches!(
+            decode(pickle, None),
+            Err(IndexError::Rpa(RpaError::FormatRecord))
+        ));
+        assert!(matches!(
+            decode(&PROTOCOL_2[..40], Some(KEY)),
+            Err(IndexError::Rpa(RpaError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn should_reject_prefixes_longer_than_records() {
+        // `{"a.txt": [(10, 1, b"PK")]}`
+        let pickle = b"\x80\x02}q\x00X\x05\x00\x00\x00a.txtq\x01]q\x02K\x0aK\x01c_codecs\nencode\nq\x03X\x02\x00\x00\x00PKq\x04X\x06\x00\x00\x00latin1q\x05\x86q\x06Rq\x07\x87q\x08as.";
+        assert!(matches!(
+            decode(pickle, None),
+            Err(IndexError::Rpa(RpaError::FormatRecord))
+        ));
+
+        let value = Record::new(10, 1, Some(b"PK".to_vec()), None).into_value();
+        assert!(matches!(
+            Record::from_value(value, None),
+            Err(RpaError::FormatRecord)
+        ));
+    }
+}
@@ -6,20 +6,59 @@
 //!
 //! ```toml
 //! [dependencies]
-//! warpalib = "0.3.0"
+//! warpalib = "0.4.0"
 //! ```
+//!
+//! # Features
+//!
+//! - `glob` - Select content using glob patterns.
+//! - `mmap` - Memory map archives with `MmapArchive` and borrow their data.
+//! - `rayon` - Read content on a rayon thread pool while writing archives.
+//! - `serde` - (De)serialize raw index entries, for example to edit an index as json.
 
 mod archive;
+mod archive_set;
 mod content;
+mod copy;
+mod entry;
 mod error;
+mod extract;
+mod index;
+mod lock;
+mod parallel;
+mod path;
+mod progress;
+mod raw_index;
 mod record;
+mod save;
+mod shared;
+mod source;
+mod split;
+mod temp;
 mod version;
 
 #[cfg(feature = "glob")]
 mod glob;
 
-pub use archive::RenpyArchive;
-pub use content::{Content, ContentMap};
+#[cfg(feature = "mmap")]
+mod mmap;
+
+pub use archive::{write_header, write_index, RenpyArchive};
+pub use archive_set::{ArchiveSet, Provider};
+pub use content::{Content, ContentMap, EntryWriter, MergePolicy};
+pub use entry::{Entry, EntryKind};
 pub use error::{RpaError, RpaResult};
+pub use extract::{
+    free_path, ExtractOptions, ExtractOutcome, ExtractProgress, ExtractSummary, OverwritePolicy,
+};
+pub use lock::{ArchiveLock, LockWait};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapArchive;
+pub use parallel::ParallelOptions;
+pub use path::{validate_path, validate_target, ArchivePath, UnsafeReason};
+pub use progress::{CancelToken, Hooks, ProgressObserver};
+pub use raw_index::{dump_index, load_index, IndexEntry, RawIndex};
 pub use record::Record;
+pub use shared::{PositionalReader, SharedArchive};
+pub use source::{ContentSource, ReadOnce, ReaderFn};
 pub use version::RpaVersion;
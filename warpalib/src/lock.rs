@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, trace};
+
+use crate::{RpaError, RpaResult};
+
+/// Interval between attempts to take a lock held by another process.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Defines how long to wait for a lock held by another process.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum LockWait {
+    /// Wait until the lock is released.
+    #[default]
+    Block,
+
+    /// Fail if the lock is held.
+    FailFast,
+
+    /// Wait at most this long for the lock to be released.
+    Timeout(Duration),
+}
+
+/// An advisory lock on an archive, held for a whole read-modify-write cycle.
+///
+/// The lock is taken on a hidden `.<name>.lock` file next to the archive rather
+/// than the archive itself, as saving with [`RenpyArchive::save_atomic`] replaces
+/// the archive file. Lock files are left in place, removing them would let two
+/// processes lock different files. Links to the same archive share a lock.
+///
+/// Locks are advisory: only processes taking the lock are kept from modifying the
+/// archive at the same time. The lock is released when dropped.
+///
+/// [`RenpyArchive::save_atomic`]: crate::RenpyArchive::save_atomic
+///
+/// # Examples
+///
+/// ```rust
+/// use warpalib::{ArchiveLock, LockWait, RenpyArchive, RpaError};
+///
+/// let path = std::env::temp_dir().join("warpalib-lock-example.rpa");
+///
+/// let lock = ArchiveLock::acquire(&path, LockWait::Block).expect("Failed to lock archive");
+/// assert!(matches!(
+///     ArchiveLock::acquire(&path, LockWait::FailFast),
+///     Err(RpaError::Locked(_))
+/// ));
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+/// archive.save_atomic(&path).unwrap();
+/// drop(lock);
+///
+/// assert!(ArchiveLock::acquire(&path, LockWait::FailFast).is_ok());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ArchiveLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl ArchiveLock {
+    /// Take the lock of the archive at `path`, which does not need to exist, waiting
+    /// for other processes holding it as set by `wait`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Locked` if the lock is still held once done waiting,
+    /// and any error raised while opening the lock file.
+    pub fn acquire(path: &Path, wait: LockWait) -> RpaResult<Self> {
+        let lock_path = lock_path(path)?;
+        trace!("Locking {}", lock_path.display());
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)?;
+
+        let deadline = match wait {
+            LockWait::Block => {
+                file.lock()?;
+                return Ok(Self::locked(file, lock_path));
+            }
+            LockWait::FailFast => Instant::now(),
+            LockWait::Timeout(timeout) => Instant::now() + timeout,
+        };
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self::locked(file, lock_path)),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(RpaError::Locked(path.to_path_buf()));
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// The path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn locked(file: File, path: PathBuf) -> Self {
+        debug!("Locked {}", path.display());
+        Self { _file: file, path }
+    }
+}
+
+/// The lock file of the archive at `path`, next to the target of a symlink.
+fn lock_path(path: &Path) -> io::Result<PathBuf> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e),
+    };
+
+    match target.file_name() {
+        Some(name) => Ok(target.with_file_name(format!(".{}.lock", name.to_string_lossy()))),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no archive file name to lock in '{}'", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("warpalib-lock-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("a.rpa")
+    }
+
+    #[test]
+    fn should_time_out_while_locked() {
+        let path = archive_path("timeout");
+        let lock = ArchiveLock::acquire(&path, LockWait::FailFast).unwrap();
+
+        let timeout = Duration::from_millis(200);
+        let start = Instant::now();
+        let result = ArchiveLock::acquire(&path, LockWait::Timeout(timeout));
+        assert!(matches!(result, Err(RpaError::Locked(locked)) if locked == path));
+        assert!(start.elapsed() >= timeout);
+
+        drop(lock);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn should_lock_once_released_before_timeout() {
+        let path = archive_path("released");
+        let lock = ArchiveLock::acquire(&path, LockWait::FailFast).unwrap();
+        let dir = path.parent().unwrap().canonicalize().unwrap();
+        assert_eq!(lock.path(), dir.join(".a.rpa.lock"));
+
+        let waiting = thread::spawn({
+            let path = path.clone();
+            move || ArchiveLock::acquire(&path, LockWait::Timeout(Duration::from_secs(10)))
+        });
+        thread::sleep(POLL_INTERVAL * 2);
+        drop(lock);
+
+        assert!(waiting.join().unwrap().is_ok());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
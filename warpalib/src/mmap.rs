@@ -0,0 +1,164 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Cursor},
+    ops::{Deref, DerefMut},
+    path::Path,
+};
+
+use log::trace;
+use memmap2::Mmap;
+
+use crate::{
+    extract::{extract_parallel, select},
+    ArchivePath, Content, ExtractOptions, ExtractSummary, Record, RenpyArchive, RpaError,
+    RpaResult,
+};
+
+/// A renpy archive memory mapped from a file.
+///
+/// The data of records can be borrowed straight from the mapped memory with
+/// [`MmapArchive::get_bytes`]. The archive dereferences to a [`RenpyArchive`]
+/// reading from the mapped memory.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::fs::File;
+/// use warpalib::{MmapArchive, RenpyArchive};
+///
+/// let path = std::env::temp_dir().join("warpalib-mmap-example.rpa");
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("script.rpy", b"label start:".to_vec());
+/// archive.flush(&mut File::create(&path).unwrap()).unwrap();
+///
+/// let archive = MmapArchive::open(&path).expect("Failed to map archive");
+/// let bytes = archive.get_bytes("script.rpy").unwrap();
+/// assert_eq!(&bytes[..], b"label start:");
+/// # std::fs::remove_file(path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MmapArchive {
+    archive: RenpyArchive<Cursor<Mmap>>,
+}
+
+impl MmapArchive {
+    /// Map the archive file at `path` into memory and parse it.
+    ///
+    /// The file must not be modified while it is mapped. Changes made to it by this
+    /// or another process are visible through the mapping and truncating it leads to
+    /// the process being killed when the missing data is accessed.
+    pub fn open(path: &Path) -> RpaResult<Self> {
+        trace!("Mapping archive from file: {}", path.display());
+
+        let file = File::open(path)?;
+        // SAFETY: The requirement of the file not being modified is documented above.
+        let mmap = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        mmap.advise(memmap2::Advice::WillNeed)?;
+
+        let mut reader = Cursor::new(mmap);
+        let version = match path.file_name() {
+            Some(name) => RenpyArchive::version(&mut reader, &name.to_string_lossy())?,
+            None => RenpyArchive::version(&mut reader, "")?,
+        };
+        let (offset, key, content) = RenpyArchive::metadata(&mut reader, &version)?;
+
+        Ok(Self {
+            archive: RenpyArchive {
+                reader,
+                key,
+                offset,
+                version,
+                content,
+            },
+        })
+    }
+
+    /// The mapped data of the whole archive file.
+    pub fn data(&self) -> &[u8] {
+        self.archive.reader.get_ref()
+    }
+
+    /// Return the data of the file at `path` in the archive.
+    ///
+    /// Records without a prefix are borrowed from the mapped memory. Records with a
+    /// prefix are copied into an owned buffer with the prefix prepended. Raw content
+    /// is borrowed, while other content is read into an owned buffer.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `NotFound` if `path` is not present in the archive,
+    /// an `UnexpectedEof` error if a record points outside of the archive file, and any
+    /// errors raised while reading other content.
+    pub fn get_bytes<P: Into<ArchivePath>>(&self, path: P) -> RpaResult<Cow<'_, [u8]>> {
+        fn inner(archive: &MmapArchive, path: ArchivePath) -> RpaResult<Cow<'_, [u8]>> {
+            match archive.content.get(&path) {
+                Some(Content::Record(record)) => {
+                    let data = slice(archive.data(), record)?;
+                    match record.prefix.as_deref() {
+                        None => Ok(Cow::Borrowed(data)),
+                        Some(prefix) => Ok(Cow::Owned([prefix, data].concat())),
+                    }
+                }
+                Some(Content::Raw(data)) => Ok(Cow::Borrowed(data)),
+                Some(content) => {
+                    let mut data = Vec::new();
+                    content.copy_to(&mut Cursor::new(archive.data()), &mut data)?;
+                    Ok(Cow::Owned(data))
+                }
+                None => Err(RpaError::NotFound(path)),
+            }
+        }
+        inner(self, path.into())
+    }
+
+    /// Extract the content of the archive into `dir` on multiple threads.
+    ///
+    /// This behaves like [`RenpyArchive::extract_to`], with `options.threads` threads
+    /// each reading a contiguous part of the mapped memory.
+    pub fn extract_to(&self, dir: &Path, options: &ExtractOptions) -> ExtractSummary {
+        let entries = select(&self.content, options);
+        extract_parallel(|| Cursor::new(self.data()), None, dir, &entries, options)
+    }
+
+    /// Consume and return the archive reading from the mapped memory.
+    pub fn into_inner(self) -> RenpyArchive<Cursor<Mmap>> {
+        self.archive
+    }
+}
+
+impl Deref for MmapArchive {
+    type Target = RenpyArchive<Cursor<Mmap>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.archive
+    }
+}
+
+impl DerefMut for MmapArchive {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.archive
+    }
+}
+
+/// The data of the `record` in `data`, excluding its prefix.
+fn slice<'d>(data: &'d [u8], record: &Record) -> io::Result<&'d [u8]> {
+    let prefix_length = record.prefix.as_ref().map_or(0, |p| p.len()) as u64;
+    let end = record
+        .start
+        .saturating_add(record.length)
+        .saturating_sub(prefix_length);
+
+    usize::try_from(record.start)
+        .ok()
+        .zip(usize::try_from(end).ok())
+        .and_then(|(start, end)| data.get(start..end))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "record points outside of the archive",
+            )
+        })
+}
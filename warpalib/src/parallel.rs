@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, Cursor, Read, Seek, Write},
+    mem,
+    num::NonZeroUsize,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use log::{debug, trace};
+
+use crate::{
+    archive::write_written_index, write_header, ArchivePath, Content, ContentMap, Hooks, Record,
+    RenpyArchive, RpaResult,
+};
+
+/// Options for writing an archive with [`RenpyArchive::flush_parallel`].
+#[derive(Clone, Debug)]
+pub struct ParallelOptions {
+    /// Number of threads reading content. Defaults to the available parallelism.
+    pub threads: usize,
+
+    /// Maximum number of entries read ahead of the writer.
+    pub read_ahead: usize,
+
+    /// Content larger than this many bytes is copied by the writer instead of being
+    /// read ahead into memory.
+    pub max_buffered: u64,
+
+    /// Progress observer and cancellation token of the flush.
+    pub hooks: Hooks,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            read_ahead: 64,
+            max_buffered: 8 << 20,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+/// Content of an entry read by a worker ahead of the writer.
+enum Prefetched {
+    /// The whole content.
+    Data(Vec<u8>),
+
+    /// The content is too large or its length is unknown, left for the writer to copy.
+    Deferred,
+}
+
+/// The index of an entry along with its content read by a worker.
+type Prefetch = (usize, io::Result<Prefetched>);
+
+/// Workers reading the content of entries sent to them by the writer.
+struct Workers<'a> {
+    entries: &'a [(ArchivePath, Content)],
+    jobs: Mutex<Receiver<usize>>,
+    results: Sender<Prefetch>,
+    max_buffered: u64,
+}
+
+impl<'a> Workers<'a> {
+    /// Create workers for the `entries` along with the channels used by the writer
+    /// to send them jobs and receive the results.
+    fn new(
+        entries: &'a [(ArchivePath, Content)],
+        options: &ParallelOptions,
+    ) -> (Self, Sender<usize>, Receiver<Prefetch>) {
+        let (jobs, job_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let workers = Self {
+            entries,
+            jobs: Mutex::new(job_receiver),
+            results: result_sender,
+            max_buffered: options.max_buffered,
+        };
+
+        (workers, jobs, results)
+    }
+
+    /// Read entries until the writer stops sending jobs.
+    fn run(&self) {
+        loop {
+            let index = match self.jobs.lock() {
+                Ok(jobs) => match jobs.recv() {
+                    Ok(index) => index,
+                    Err(_) => return,
+                },
+                Err(_) => return,
+            };
+
+            let result = prefetch(&self.entries[index].1, self.max_buffered);
+            if self.results.send((index, result)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl<R> RenpyArchive<R>
+where
+    R: Seek + BufRead,
+{
+    /// Consume and write the archive to the `writer`, reading content on multiple
+    /// threads.
+    ///
+    /// Files, sources and records of other archives are read concurrently by
+    /// `options.threads` threads ahead of the writer, which copies them into the
+    /// `writer` one after another. At most `options.read_ahead` entries are held in
+    /// memory at once. Records of this archive and raw content are copied by the writer.
+    ///
+    /// Content is written in the order of [`ContentMap::sorted_by_offset`], so the
+    /// output does not depend on the order the threads finish reading in.
+    ///
+    /// Progress is reported to and cancellation checked on `options.hooks` as entries
+    /// are written. Returns the index as written, like [`RenpyArchive::flush_with`].
+    ///
+    /// This pays off when opening and reading content has latency, such as many small
+    /// files on network storage. For content that is quick to read, [`RenpyArchive::flush`]
+    /// avoids the overhead of handing data between threads.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use warpalib::{ParallelOptions, ReaderFn, RenpyArchive};
+    ///
+    /// let write = || {
+    ///     let mut archive = RenpyArchive::new();
+    ///     for i in 0..32u8 {
+    ///         let source = ReaderFn::new(move || Ok(Cursor::new(vec![i; 64]))).with_length(64);
+    ///         archive.content.insert_source(format!("{i}.bin"), source);
+    ///     }
+    ///
+    ///     let mut writer = Cursor::new(vec![]);
+    ///     archive
+    ///         .flush_parallel(&mut writer, &ParallelOptions::default())
+    ///         .expect("Failed to write archive");
+    ///     writer.into_inner()
+    /// };
+    ///
+    /// let data = write();
+    /// assert_eq!(data, write());
+    ///
+    /// let mut archive = RenpyArchive::read(Cursor::new(data)).unwrap();
+    /// let mut buffer = vec![];
+    /// archive.copy_file("7.bin", &mut buffer).unwrap();
+    /// assert_eq!(buffer, vec![7u8; 64]);
+    /// ```
+    pub fn flush_parallel<W: Seek + Write>(
+        mut self,
+        writer: &mut W,
+        options: &ParallelOptions,
+    ) -> RpaResult<ContentMap> {
+        let threads = options.threads.max(1);
+        let entries = mem::take(&mut self.content).into_sorted_by_offset();
+        let (workers, jobs, results) = Workers::new(&entries, options);
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| workers.run());
+            }
+            self.write_pipelined(writer, &entries, jobs, results, options)
+        })
+    }
+
+    /// Consume and write the archive to the `writer`, reading content on the threads
+    /// of the rayon `pool`.
+    ///
+    /// This behaves like [`RenpyArchive::flush_parallel`] with one reader per thread
+    /// of the pool. The writer runs on the calling thread, which should not be a thread
+    /// of the `pool` itself.
+    #[cfg(feature = "rayon")]
+    pub fn flush_parallel_in<W: Seek + Write>(
+        mut self,
+        writer: &mut W,
+        options: &ParallelOptions,
+        pool: &rayon::ThreadPool,
+    ) -> RpaResult<ContentMap> {
+        let entries = mem::take(&mut self.content).into_sorted_by_offset();
+        let (workers, jobs, results) = Workers::new(&entries, options);
+
+        pool.in_place_scope(|scope| {
+            for _ in 0..pool.current_num_threads() {
+                scope.spawn(|_| workers.run());
+            }
+            self.write_pipelined(writer, &entries, jobs, results, options)
+        })
+    }
+
+    /// Write the `entries` in order, handing content to be read ahead to the workers
+    /// through `jobs` and collecting it from `results`.
+    fn write_pipelined<W: Seek + Write>(
+        &mut self,
+        writer: &mut W,
+        entries: &[(ArchivePath, Content)],
+        jobs: Sender<usize>,
+        results: Receiver<Prefetch>,
+        options: &ParallelOptions,
+    ) -> RpaResult<ContentMap> {
+        trace!("Commencing parallel archive flush");
+
+        // Write a placeholder header to be filled later.
+        let header_length = self.version.header_length()?;
+        writer.write_all(&vec![0u8; header_length])?;
+        let mut offset = header_length as u64;
+
+        let stopped = || io::Error::other("archive readers stopped unexpectedly");
+        let read_ahead = options.read_ahead.max(1);
+        let mut pending = HashMap::new();
+        let mut dispatched = 0;
+        let mut indexes = Vec::with_capacity(entries.len());
+        let hooks = &options.hooks;
+        hooks.started(entries.iter().map(|(_, content)| content));
+
+        for (index, (path, content)) in entries.iter().enumerate() {
+            // Keep the workers busy up to `read_ahead` entries ahead of the writer.
+            while dispatched < entries.len() && dispatched < index + read_ahead {
+                if is_prefetched(&entries[dispatched].1) {
+                    jobs.send(dispatched).map_err(|_| stopped())?;
+                }
+                dispatched += 1;
+            }
+
+            let length = if is_prefetched(content) {
+                let prefetched = loop {
+                    if let Some(prefetched) = pending.remove(&index) {
+                        break prefetched;
+                    }
+                    let (done, prefetched) = results.recv().map_err(|_| stopped())?;
+                    pending.insert(done, prefetched);
+                };
+
+                match prefetched? {
+                    Prefetched::Data(data) => hooks.write(path, &data, writer)?,
+                    Prefetched::Deferred => hooks.copy(path, content, &mut self.reader, writer)?,
+                }
+            } else {
+                hooks.copy(path, content, &mut self.reader, writer)?
+            };
+            debug!("Written content from path ({path}) length ({length} bytes)");
+
+            indexes.push((path.clone(), Record::new(offset, length, None, None)));
+            offset += length;
+        }
+
+        // Stop the workers.
+        drop(jobs);
+
+        let written = write_written_index(writer, indexes, self.key)?;
+        write_header(writer, &self.version, offset, self.key)?;
+
+        writer.flush()?;
+        debug!("Done writing archive");
+
+        Ok(written)
+    }
+}
+
+/// Whether the content is read ahead by the workers. Other content is either in
+/// memory or needs the reader of the archive.
+fn is_prefetched(content: &Content) -> bool {
+    matches!(
+        content,
+        Content::File(_) | Content::ArchiveRecord { .. } | Content::Source(_)
+    )
+}
+
+/// Read the whole `content` into memory if it is not larger than `max_buffered`.
+fn prefetch(content: &Content, max_buffered: u64) -> io::Result<Prefetched> {
+    // Avoid looking up the file twice, latency is what is being hidden here.
+    if let Content::File(path) = content {
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len();
+        if length > max_buffered {
+            return Ok(Prefetched::Deferred);
+        }
+
+        let mut data = Vec::with_capacity(length as usize);
+        file.read_to_end(&mut data)?;
+        return Ok(Prefetched::Data(data));
+    }
+
+    match content.length() {
+        Ok(length) if length <= max_buffered => {
+            let mut data = Vec::with_capacity(length as usize);
+            content.copy_to(&mut Cursor::new([]), &mut data)?;
+            Ok(Prefetched::Data(data))
+        }
+        _ => Ok(Prefetched::Deferred),
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR},
+    str::FromStr,
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{RpaError, RpaResult};
+
+/// A normalized path of content in an archive.
+///
+/// Archive paths always use `/` as separator, are valid UTF-8 and are normalized to
+/// unicode NFC, so paths from archives built on any platform compare equal.
+///
+/// # Examples
+///
+/// ```rust
+/// use warpalib::ArchivePath;
+///
+/// let path = ArchivePath::from("images\\bg.png");
+/// assert_eq!(path.as_str(), "images/bg.png");
+///
+/// // Decomposed (NFD) names created on macOS match their composed form.
+/// assert_eq!(ArchivePath::from("cafe\u{301}.png"), ArchivePath::from("caf\u{e9}.png"));
+///
+/// assert!(path.eq_ignore_case(&ArchivePath::from("Images/BG.png")));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArchivePath(String);
+
+impl ArchivePath {
+    /// Create an archive path from a string, normalizing separators and unicode.
+    pub fn new(path: &str) -> Self {
+        Self(path.replace('\\', "/").nfc().collect())
+    }
+
+    /// Create an archive path from a filesystem path.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `NonUtf8Path` if the path is not valid UTF-8.
+    pub fn from_path(path: &Path) -> RpaResult<Self> {
+        match path.to_str() {
+            Some(s) => Ok(Self::new(s)),
+            None => Err(RpaError::NonUtf8Path(path.to_path_buf())),
+        }
+    }
+
+    /// The normalized path as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Convert into a relative filesystem path using the platform separator.
+    pub fn to_path(&self) -> PathBuf {
+        PathBuf::from(self.0.replace('/', MAIN_SEPARATOR_STR))
+    }
+
+    /// The path folded to lowercase, used for case-insensitive comparison.
+    pub fn fold(&self) -> String {
+        self.0.to_lowercase()
+    }
+
+    /// Compare two paths ignoring case, the way renpy resolves names.
+    pub fn eq_ignore_case(&self, other: &ArchivePath) -> bool {
+        self == other || self.fold() == other.fold()
+    }
+
+    /// Check that the path stays inside the directory it is extracted to.
+    ///
+    /// See [`validate_path`] for details.
+    pub fn validate(&self) -> RpaResult<()> {
+        validate_path(Path::new(&self.0))
+    }
+}
+
+impl Display for ArchivePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ArchivePath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ArchivePath {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<String> for ArchivePath {
+    fn from(path: String) -> Self {
+        // Most paths are already normalized, reuse their allocation.
+        if !path.contains('\\') && unicode_normalization::is_nfc(&path) {
+            return Self(path);
+        }
+        Self::new(&path)
+    }
+}
+
+impl From<&String> for ArchivePath {
+    fn from(path: &String) -> Self {
+        Self::new(path)
+    }
+}
+
+impl From<&ArchivePath> for ArchivePath {
+    fn from(path: &ArchivePath) -> Self {
+        path.clone()
+    }
+}
+
+impl TryFrom<&Path> for ArchivePath {
+    type Error = RpaError;
+
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        Self::from_path(path)
+    }
+}
+
+impl TryFrom<PathBuf> for ArchivePath {
+    type Error = RpaError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::from_path(&path)
+    }
+}
+
+impl FromStr for ArchivePath {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+/// Reasons an archive path is unsafe to extract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnsafeReason {
+    /// The path is empty.
+    Empty,
+
+    /// The path contains a nul byte.
+    Nul,
+
+    /// The path starts at the root of the filesystem.
+    Absolute,
+
+    /// The path starts with a drive or UNC prefix.
+    Prefix,
+
+    /// The path contains a `..` component.
+    ParentDir,
+
+    /// A directory or file on the way to the path is a symbolic link.
+    Symlink,
+}
+
+impl Display for UnsafeReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsafeReason::Empty => write!(f, "empty path"),
+            UnsafeReason::Nul => write!(f, "contains a nul byte"),
+            UnsafeReason::Absolute => write!(f, "absolute path"),
+            UnsafeReason::Prefix => write!(f, "drive prefix"),
+            UnsafeReason::ParentDir => write!(f, "parent directory component"),
+            UnsafeReason::Symlink => write!(f, "symbolic link in path"),
+        }
+    }
+}
+
+/// Check that an archive `path` stays inside the directory it is extracted to.
+///
+/// Both `/` and `\` are treated as separators, since archives may be built on any
+/// platform.
+///
+/// # Errors
+///
+/// This function returns `UnsafePath` if the path is empty, absolute, has a drive prefix,
+/// contains a `..` component or contains a nul byte.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::path::Path;
+/// use warpalib::validate_path;
+///
+/// assert!(validate_path(Path::new("images/bg.png")).is_ok());
+/// assert!(validate_path(Path::new("../../.bashrc")).is_err());
+/// assert!(validate_path(Path::new("/etc/passwd")).is_err());
+/// assert!(validate_path(Path::new("C:\\Windows")).is_err());
+/// ```
+pub fn validate_path(path: &Path) -> RpaResult<()> {
+    let unsafe_path = |reason| Err(RpaError::UnsafePath(path.to_path_buf(), reason));
+
+    let s = path.to_string_lossy();
+    let bytes = s.as_bytes();
+
+    if s.is_empty() {
+        return unsafe_path(UnsafeReason::Empty);
+    }
+
+    if s.contains('\0') {
+        return unsafe_path(UnsafeReason::Nul);
+    }
+
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return unsafe_path(UnsafeReason::Prefix);
+    }
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) => return unsafe_path(UnsafeReason::Prefix),
+            Component::RootDir => return unsafe_path(UnsafeReason::Absolute),
+            _ => (),
+        }
+    }
+
+    if s.starts_with(['/', '\\']) {
+        return unsafe_path(UnsafeReason::Absolute);
+    }
+
+    if s.split(['/', '\\']).any(|segment| segment == "..") {
+        return unsafe_path(UnsafeReason::ParentDir);
+    }
+
+    Ok(())
+}
+
+/// Check that no existing directory or file between `root` and `root.join(path)` is a
+/// symbolic link, which would allow writes to escape `root`.
+///
+/// `root` itself is trusted and may be a symbolic link.
+///
+/// # Errors
+///
+/// This function returns `UnsafePath` if a symbolic link is found, and forwards errors
+/// raised while reading metadata.
+pub fn validate_target(root: &Path, path: &Path) -> RpaResult<()> {
+    let mut current = root.to_path_buf();
+    for component in path.components() {
+        current.push(component);
+
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(RpaError::UnsafePath(
+                    path.to_path_buf(),
+                    UnsafeReason::Symlink,
+                ))
+            }
+            Ok(_) => (),
+            // Nothing further down the path exists yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
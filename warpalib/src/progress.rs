@@ -0,0 +1,317 @@
+use std::{
+    fmt::Debug,
+    io::{self, Read, Seek, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    copy::{write_chunked, Between},
+    ArchivePath, Content, RpaError, RpaResult,
+};
+
+/// Observes the progress of writing and extracting archives.
+///
+/// Every method does nothing by default. Methods may be called from multiple threads
+/// at once when content is copied in parallel.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::{io::Cursor, sync::{atomic::{AtomicU64, Ordering}, Arc}};
+/// use warpalib::{Hooks, ProgressObserver, RenpyArchive};
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicU64);
+///
+/// impl ProgressObserver for Counter {
+///     fn bytes_copied(&self, bytes: u64) {
+///         self.0.fetch_add(bytes, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter::default());
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+/// archive
+///     .flush_with(&mut Cursor::new(vec![]), &Hooks::new().with_observer(counter.clone()))
+///     .unwrap();
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 5);
+/// ```
+pub trait ProgressObserver: Send + Sync {
+    /// Called before any content is copied with the number of entries and their
+    /// total length in bytes. Entries with an unknown length do not count towards
+    /// the total.
+    fn started(&self, _entries: usize, _bytes: u64) {}
+
+    /// Called before the content of `path` is copied.
+    fn entry_started(&self, _path: &ArchivePath) {}
+
+    /// Called as content is copied with the number of bytes copied since the last call.
+    fn bytes_copied(&self, _bytes: u64) {}
+
+    /// Called after the content of `path` is copied.
+    fn entry_finished(&self, _path: &ArchivePath) {}
+}
+
+/// A token cancelling the operations it is passed to.
+///
+/// Cancellation is cooperative: operations check the token between entries and
+/// while copying content, then stop with [`RpaError::Cancelled`]. Clones of the token
+/// share its state, so it can be cancelled from another thread or a signal handler.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use warpalib::{CancelToken, Hooks, RenpyArchive, RpaError};
+///
+/// let token = CancelToken::new();
+/// token.cancel();
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+/// let result = archive.flush_with(&mut Cursor::new(vec![]), &Hooks::new().with_cancel(token));
+/// assert!(matches!(result, Err(RpaError::Cancelled)));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the operations using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Return `Cancelled` if the token was cancelled.
+    pub fn check(&self) -> RpaResult<()> {
+        match self.is_cancelled() {
+            true => Err(RpaError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A progress observer and a cancellation token passed to long operations.
+///
+/// Content is copied in chunks, reporting progress and checking cancellation between
+/// them on the unwrapped reader and writer, so copies between files keep their fastest
+/// path.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    observer: Option<Arc<dyn ProgressObserver>>,
+    cancel: Option<CancelToken>,
+}
+
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("observer", &self.observer.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl Hooks {
+    /// Create hooks without an observer or a cancellation token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report progress to the `observer`.
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Stop when the `token` is cancelled.
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Whether the cancellation token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Return `Cancelled` if the cancellation token was cancelled.
+    pub fn check(&self) -> RpaResult<()> {
+        match self.cancel.as_ref() {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Report the start of an operation copying `contents`.
+    pub(crate) fn started<'c, I>(&self, contents: I)
+    where
+        I: ExactSizeIterator<Item = &'c Content>,
+    {
+        if let Some(observer) = self.observer.as_ref() {
+            let entries = contents.len();
+            let bytes = contents.filter_map(|c| c.length().ok()).sum();
+            observer.started(entries, bytes);
+        }
+    }
+
+    /// Copy the `content` of `path` into the `writer`, reporting its progress.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Cancelled` if cancelled before or while copying, in which case the
+    /// content may be partially written.
+    pub(crate) fn copy<R, W>(
+        &self,
+        path: &ArchivePath,
+        content: &Content,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> RpaResult<u64>
+    where
+        R: Seek + Read,
+        W: Write,
+    {
+        self.copy_with(path, |between| {
+            content.copy_chunked(reader, writer, between)
+        })
+    }
+
+    /// Run `copy` copying the content of `path`, reporting its progress.
+    ///
+    /// `copy` is passed a callback to call with the bytes copied after each chunk,
+    /// which fails once cancelled.
+    pub(crate) fn copy_with<F>(&self, path: &ArchivePath, copy: F) -> RpaResult<u64>
+    where
+        F: FnOnce(&mut Between) -> io::Result<u64>,
+    {
+        self.check()?;
+        self.entry_started(path);
+        let result = copy(&mut |bytes| self.copied(bytes));
+        self.entry_finished(path, result)
+    }
+
+    /// Write the `data` of `path` into the `writer`, reporting its progress.
+    pub(crate) fn write<W: Write>(
+        &self,
+        path: &ArchivePath,
+        data: &[u8],
+        writer: &mut W,
+    ) -> RpaResult<u64> {
+        self.copy_with(path, |between| write_chunked(data, writer, between))
+    }
+
+    fn entry_started(&self, path: &ArchivePath) {
+        if let Some(observer) = self.observer.as_ref() {
+            observer.entry_started(path);
+        }
+    }
+
+    /// Report the `bytes` copied, failing once cancelled.
+    fn copied(&self, bytes: u64) -> io::Result<()> {
+        if let Some(observer) = self.observer.as_ref() {
+            observer.bytes_copied(bytes);
+        }
+        match self.is_cancelled() {
+            true => Err(io::Error::other(RpaError::Cancelled)),
+            false => Ok(()),
+        }
+    }
+
+    fn entry_finished(&self, path: &ArchivePath, result: io::Result<u64>) -> RpaResult<u64> {
+        if result.is_err() {
+            // Copies fail with an io error on cancellation.
+            self.check()?;
+        }
+        let length = result?;
+
+        if let Some(observer) = self.observer.as_ref() {
+            observer.entry_finished(path);
+        }
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Mutex};
+
+    use crate::{copy::CHUNK, Record};
+
+    use super::*;
+
+    /// Records the bytes copied and cancels its token after the first chunk.
+    #[derive(Default)]
+    struct Recorder {
+        chunks: Mutex<Vec<u64>>,
+        cancel: Option<CancelToken>,
+    }
+
+    impl ProgressObserver for Recorder {
+        fn bytes_copied(&self, bytes: u64) {
+            self.chunks.lock().unwrap().push(bytes);
+            if let Some(token) = self.cancel.as_ref() {
+                token.cancel();
+            }
+        }
+    }
+
+    fn content() -> Content {
+        Content::Record(Record::new(0, CHUNK * 2 + 1, Some(b"> ".to_vec()), None))
+    }
+
+    #[test]
+    fn should_report_progress_between_chunks() {
+        let recorder = Arc::new(Recorder::default());
+        let hooks = Hooks::new().with_observer(recorder.clone());
+
+        let mut reader = Cursor::new(vec![1u8; CHUNK as usize * 3]);
+        let mut writer = Vec::new();
+        let copied = hooks
+            .copy(&"a.bin".into(), &content(), &mut reader, &mut writer)
+            .unwrap();
+
+        assert_eq!(copied, CHUNK * 2 + 1);
+        assert_eq!(*recorder.chunks.lock().unwrap(), [2, CHUNK, CHUNK - 1]);
+    }
+
+    #[test]
+    fn should_cancel_between_chunks() {
+        let token = CancelToken::new();
+        let recorder = Arc::new(Recorder {
+            cancel: Some(token.clone()),
+            ..Default::default()
+        });
+        let hooks = Hooks::new()
+            .with_observer(recorder.clone())
+            .with_cancel(token);
+
+        let content = Content::Raw(vec![1u8; CHUNK as usize * 2]);
+        let mut writer = Vec::new();
+        let result = hooks.copy(
+            &"a.bin".into(),
+            &content,
+            &mut Cursor::new(vec![]),
+            &mut writer,
+        );
+
+        assert!(matches!(result, Err(RpaError::Cancelled)));
+        assert_eq!(writer.len() as u64, CHUNK);
+        assert!(matches!(
+            hooks.write(&"b.bin".into(), b"data", &mut writer),
+            Err(RpaError::Cancelled)
+        ));
+    }
+}
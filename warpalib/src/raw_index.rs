@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek},
+    path::Path,
+};
+
+use log::{debug, warn};
+
+use crate::{
+    save::{resolve_target, write_atomic},
+    write_header, write_index, Record, RenpyArchive, RpaError, RpaResult,
+};
+
+/// A deobfuscated index entry, as edited by hand when repairing an archive.
+///
+/// With the `serde` feature, the entry (de)serializes with the prefix written as hex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexEntry {
+    /// Index of starting byte of data.
+    pub start: u64,
+
+    /// The length of the data including the prefix.
+    pub length: u64,
+
+    /// The prefix added before the data.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none", with = "hex")
+    )]
+    pub prefix: Option<Vec<u8>>,
+}
+
+impl From<Record> for IndexEntry {
+    fn from(record: Record) -> Self {
+        Self {
+            start: record.start,
+            length: record.length,
+            prefix: record.prefix,
+        }
+    }
+}
+
+impl TryFrom<IndexEntry> for Record {
+    type Error = RpaError;
+
+    /// Convert the entry into a record, failing with `FormatRecord` if the prefix is
+    /// longer than the length.
+    fn try_from(entry: IndexEntry) -> RpaResult<Self> {
+        Record::new(entry.start, entry.length, entry.prefix, None).checked()
+    }
+}
+
+/// The index of an archive mapped by the raw paths stored in it, without normalizing
+/// or validating them.
+pub type RawIndex = BTreeMap<String, IndexEntry>;
+
+/// Read the deobfuscated index of the archive in `reader`, identified using `name`.
+///
+/// Unlike [`RenpyArchive::read`], paths are kept as stored in the archive. Entries
+/// that cannot be parsed as records are skipped with a warning.
+///
+/// # Errors
+///
+/// This function forwards errors raised while reading the header and the index.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use warpalib::{dump_index, RenpyArchive};
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+///
+/// let mut buffer = Cursor::new(vec![]);
+/// archive.flush(&mut buffer).unwrap();
+/// buffer.set_position(0);
+///
+/// let index = dump_index(&mut buffer, "archive.rpa").unwrap();
+/// assert_eq!(index["a.txt"].length, 5);
+/// ```
+pub fn dump_index<R: BufRead + Seek>(reader: &mut R, name: &str) -> RpaResult<RawIndex> {
+    let version = RenpyArchive::version(reader, name)?;
+    let (offset, key) = RenpyArchive::header(reader, &version)?;
+
+    let mut index = RawIndex::new();
+    for (path, value) in RenpyArchive::read_index(reader, offset)? {
+        match Record::from_value(value, key) {
+            Ok(record) => {
+                index.insert(path, record.into());
+            }
+            Err(e) => warn!("Skipping entry {path}: {e}"),
+        }
+    }
+
+    Ok(index)
+}
+
+/// Atomically replace the index of the archive at `path` with `index`, keeping the
+/// data section as is.
+///
+/// The data section is copied to a temp file followed by the new index, which then
+/// replaces the archive like [`RenpyArchive::save_atomic`] does, so a crash leaves
+/// either the previous or the new archive.
+///
+/// The index is obfuscated with `key` if given, otherwise with the key of the archive.
+/// Archives without a key stay without one.
+///
+/// # Errors
+///
+/// This function returns `OutOfBounds` if an entry points outside of the data section,
+/// `FormatRecord` if its prefix is longer than its length, in which case nothing is
+/// written, and forwards errors raised while reading the header and writing the
+/// archive. The temp file is removed on errors.
+pub fn load_index(path: &Path, index: RawIndex, key: Option<u64>) -> RpaResult<()> {
+    let target = resolve_target(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut reader = BufReader::new(File::open(&target)?);
+
+    let version = RenpyArchive::version(&mut reader, &name)?;
+    let (offset, archive_key) = RenpyArchive::header(&mut reader, &version)?;
+    let header_length = version.header_length()? as u64;
+    let key = archive_key.map(|k| key.unwrap_or(k));
+
+    // The archive would be unreadable with entries outside of the data section.
+    let mut records = Vec::with_capacity(index.len());
+    for (path, entry) in index {
+        let record = Record::try_from(entry)?;
+        let end = record.start.checked_add(record.actual_length());
+        if record.start < header_length || end.is_none_or(|end| end > offset) {
+            return Err(RpaError::OutOfBounds(path, header_length, offset));
+        }
+        records.push((path, record));
+    }
+
+    write_atomic(&target, |file| {
+        reader.rewind()?;
+        let copied = io::copy(&mut reader.take(offset), file)?;
+        if copied != offset {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let length = write_index(file, records, key)?;
+        debug!("Written index of {length} bytes at {offset}");
+
+        write_header(file, &version, offset, key)?;
+        Ok(())
+    })?;
+    debug!("Replaced index of {}", target.display());
+
+    Ok(())
+}
+
+/// (De)serialize prefixes as hex strings.
+#[cfg(feature = "serde")]
+mod hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        prefix: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match prefix {
+            Some(prefix) => {
+                let hex = prefix
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<String>();
+                serializer.serialize_some(&hex)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(hex) => decode(&hex)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom(format!("'{hex}' is not a valid hex prefix"))),
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn decode(hex: &str) -> Option<Vec<u8>> {
+        hex.as_bytes()
+            .chunks(2)
+            .map(|pair| match pair {
+                [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn should_load_edited_index() {
+        let path = std::env::temp_dir().join(format!("warpalib-{}-index.rpa", std::process::id()));
+
+        let mut archive = RenpyArchive::new();
+        archive.content.insert_raw("a.txt", b"hello".to_vec());
+        archive.content.insert_raw("b.txt", b"world".to_vec());
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+
+        // Drop an entry and prefix another.
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut index = dump_index(&mut reader, "index.rpa").unwrap();
+        index.remove("b.txt");
+        let entry = index.get_mut("a.txt").unwrap();
+        entry.prefix = Some(b"> ".to_vec());
+        entry.length += 2;
+        load_index(&path, index, None).unwrap();
+
+        let mut archive = RenpyArchive::open(&path).unwrap();
+        assert_eq!(archive.content.len(), 1);
+
+        let mut data = vec![];
+        archive.copy_file("a.txt", &mut data).unwrap();
+        assert_eq!(data, b"> hello");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_keep_archive_when_loading_fails() {
+        let dir = std::env::temp_dir().join(format!("warpalib-{}-load", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.rpa");
+
+        let mut archive = RenpyArchive::new();
+        archive.content.insert_raw("a.txt", b"hello".to_vec());
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+        let offset = RenpyArchive::open(&path).unwrap().offset;
+
+        // Cut the archive short of the index offset in its header.
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let index = dump_index(&mut reader, "index.rpa").unwrap();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(offset - 1).unwrap();
+        let truncated = std::fs::read(&path).unwrap();
+
+        let result = load_index(&path, index, None);
+        assert!(
+            matches!(result, Err(crate::RpaError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), truncated);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_entries_outside_of_data() {
+        let path = std::env::temp_dir().join(format!("warpalib-{}-bounds.rpa", std::process::id()));
+
+        let mut archive = RenpyArchive::new();
+        archive.content.insert_raw("a.txt", b"hello".to_vec());
+        archive.flush(&mut File::create(&path).unwrap()).unwrap();
+        let original = std::fs::read(&path).unwrap();
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let index = dump_index(&mut reader, "bounds.rpa").unwrap();
+        let entry = |start, length, prefix: Option<&[u8]>| {
+            let mut index = index.clone();
+            index.insert(
+                "a.txt".into(),
+                IndexEntry {
+                    start,
+                    length,
+                    prefix: prefix.map(<[u8]>::to_vec),
+                },
+            );
+            load_index(&path, index, None)
+        };
+
+        let start = index["a.txt"].start;
+        for result in [
+            entry(0, 5, None),
+            entry(start, 6, None),
+            entry(start, u64::MAX, None),
+            entry(u64::MAX, 1, None),
+        ] {
+            assert!(matches!(result, Err(RpaError::OutOfBounds(path, _, _)) if path == "a.txt"));
+        }
+        assert!(matches!(
+            entry(start, 1, Some(b"> ")),
+            Err(RpaError::FormatRecord)
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        // The prefix is not stored in the data section.
+        entry(start, 7, Some(b"> ")).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_write_prefix_as_hex() {
+        let entry = IndexEntry {
+            start: 51,
+            length: 7,
+            prefix: Some(vec![0x3e, 0x20]),
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(json, r#"{"start":51,"length":7,"prefix":"3e20"}"#);
+        assert_eq!(serde_json::from_str::<IndexEntry>(&json).unwrap(), entry);
+
+        let entry = serde_json::from_str::<IndexEntry>(r#"{"start":51,"length":5}"#).unwrap();
+        assert_eq!(entry.prefix, None);
+        assert!(
+            serde_json::from_str::<IndexEntry>(r#"{"start":0,"length":0,"prefix":"3"}"#).is_err()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_decode_hex() {
+        assert_eq!(hex::decode("00ff10"), Some(vec![0x00, 0xff, 0x10]));
+        assert_eq!(hex::decode("abc"), None);
+        assert_eq!(hex::decode("zz"), None);
+    }
+}
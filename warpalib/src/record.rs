@@ -1,4 +1,7 @@
-use std::io::{self, Read, Seek, SeekFrom, Take, Write};
+use std::{
+    io::{self, Read, Seek, SeekFrom, Take, Write},
+    ops::Range,
+};
 
 use log::debug;
 use serde_pickle::Value;
@@ -63,7 +66,7 @@ impl Record {
     /// # Errors
     ///
     /// This function will return [`RpaError::FormatRecord`] if the format
-    /// of the value could not be recognized.
+    /// of the value could not be recognized or the prefix is longer than the length.
     pub fn from_value(value: Value, key: Option<u64>) -> RpaResult<Self> {
         debug!("Parsing index from value: {value:?}");
 
@@ -78,14 +81,23 @@ impl Record {
             _ => return Err(RpaError::FormatRecord),
         };
 
-        match (iter.next(), iter.next(), iter.next()) {
-            (Some(Value::I64(start)), Some(Value::I64(length)), None) => {
-                Ok(Self::new(start as u64, length as u64, None, key))
+        let record = match (iter.next(), iter.next(), iter.next()) {
+            (Some(Value::I64(start)), Some(Value::I64(length)), None | Some(Value::None)) => {
+                Self::new(start as u64, length as u64, None, key)
             }
             (Some(Value::I64(start)), Some(Value::I64(length)), Some(Value::Bytes(prefix))) => {
-                Ok(Self::new(start as u64, length as u64, Some(prefix), key))
+                Self::new(start as u64, length as u64, Some(prefix), key)
             }
-            _ => Err(RpaError::FormatRecord),
+            _ => return Err(RpaError::FormatRecord),
+        };
+        record.checked()
+    }
+
+    /// Return `FormatRecord` if the prefix is longer than the length including it.
+    pub(crate) fn checked(self) -> RpaResult<Self> {
+        match self.prefix_length() > self.length {
+            true => Err(RpaError::FormatRecord),
+            false => Ok(self),
         }
     }
 
@@ -112,9 +124,15 @@ impl Record {
 impl Record {
     /// The actual length of the indexed file.
     ///
-    /// This is calculated by subtracting `prefix` length from the `length`.
-    fn actual_length(&self) -> u64 {
-        self.length - self.prefix.as_ref().map(|v| v.len()).unwrap_or(0) as u64
+    /// This is calculated by subtracting `prefix` length from the `length`, and is the
+    /// number of bytes stored in the archive at `start`. Records built by hand with a
+    /// prefix longer than the length have no data.
+    pub(crate) fn actual_length(&self) -> u64 {
+        self.length.saturating_sub(self.prefix_length())
+    }
+
+    fn prefix_length(&self) -> u64 {
+        self.prefix.as_ref().map_or(0, |v| v.len() as u64)
     }
 
     /// Return a reader with limited scope into only the data specified
@@ -123,28 +141,43 @@ impl Record {
     /// # Errors
     ///
     /// This function forwards errors that occur during `Seek` to `start` offset.
-    pub fn scope<'i, 'r, R: Seek + Read>(
-        &'i self,
-        reader: &'r mut R,
-    ) -> io::Result<Take<&'r mut R>> {
+    pub fn scope<'r, R: Seek + Read>(&self, reader: &'r mut R) -> io::Result<Take<&'r mut R>> {
         reader.seek(SeekFrom::Start(self.start))?;
         let take = reader.by_ref().take(self.actual_length());
         Ok(take)
     }
 
-    /// Copy the data specified by this record from `reader` into the `writer`.
+    /// Copy the data specified by this record from `reader` into the `writer`,
+    /// returning the number of bytes written including the prefix.
     ///
     /// The process involves writing prefix if available and copying bytes starting
     /// from the offset `start` and writing a specific `length` of bytes to `writer`.
     ///
+    /// The data is copied with [`io::copy`], which on Linux copies between files within
+    /// the kernel using `copy_file_range` or `sendfile`, and falls back to a buffered copy
+    /// otherwise. Keep `reader` a [`File`](std::fs::File) or a `BufReader<File>` and
+    /// `writer` a file to take this path.
+    ///
     /// # Errors
     ///
     /// This function will forward any errors that occur during `Seek`, `Read`, and `Write`.
-    pub fn copy_section<'r, 'w, R, W>(
-        &'r self,
-        reader: &'r mut R,
-        writer: &'w mut W,
-    ) -> io::Result<u64>
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use warpalib::Record;
+    ///
+    /// let mut reader = Cursor::new(vec![1, 2, 3, 4]);
+    /// let mut writer = vec![];
+    ///
+    /// let record = Record::new(1, 4, Some(vec![9]), None);
+    /// let written = record.copy_section(&mut reader, &mut writer).unwrap();
+    ///
+    /// assert_eq!(written, 4);
+    /// assert_eq!(writer, vec![9, 2, 3, 4]);
+    /// ```
+    pub fn copy_section<'r, R, W>(&'r self, reader: &'r mut R, writer: &mut W) -> io::Result<u64>
     where
         R: Seek + Read,
         W: Write,
@@ -156,13 +189,78 @@ impl Record {
         );
 
         let mut scope = self.scope(reader)?;
+        let mut written = 0;
 
         // Append prefix to output
         if let Some(prefix) = self.prefix.as_ref() {
             debug!("Writing prefix: {} bytes", prefix.len());
             writer.write_all(&prefix[..])?;
+            written += prefix.len() as u64;
+        }
+
+        Ok(written + io::copy(&mut scope, writer)?)
+    }
+
+    /// Copy a byte `range` of the data specified by this record from `reader` into
+    /// the `writer`.
+    ///
+    /// The range is relative to the data as produced by [`Record::copy_section`], including
+    /// the prefix, and is clamped to its length.
+    ///
+    /// # Errors
+    ///
+    /// This function will forward any errors that occur during `Seek`, `Read`, and `Write`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use warpalib::Record;
+    ///
+    /// let mut reader = Cursor::new(vec![1, 2, 3, 4]);
+    /// let mut writer = vec![];
+    ///
+    /// let record = Record::new(0, 6, Some(vec![8, 9]), None);
+    /// record.copy_range(&mut reader, &mut writer, 1..4).unwrap();
+    ///
+    /// assert_eq!(writer, vec![9, 1, 2]);
+    /// ```
+    pub fn copy_range<R, W>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        range: Range<u64>,
+    ) -> io::Result<u64>
+    where
+        R: Seek + Read,
+        W: Write,
+    {
+        let end = range.end.min(self.length);
+        if range.start >= end {
+            return Ok(0);
+        }
+
+        debug!("Copying index bytes in range {}..{end}", range.start);
+
+        let mut written = 0;
+
+        // Copy the part of the prefix in range.
+        let prefix = self.prefix.as_deref().unwrap_or(&[]);
+        let prefix_length = prefix.len() as u64;
+        if range.start < prefix_length {
+            let prefix_end = end.min(prefix_length);
+            writer.write_all(&prefix[range.start as usize..prefix_end as usize])?;
+            written += prefix_end - range.start;
+        }
+
+        // Copy the part of the data in range.
+        if end > prefix_length {
+            let start = range.start.max(prefix_length) - prefix_length;
+            reader.seek(SeekFrom::Start(self.start + start))?;
+            let mut take = reader.by_ref().take(end - prefix_length - start);
+            written += io::copy(&mut take, writer)?;
         }
 
-        io::copy(&mut scope, writer)
+        Ok(written)
     }
 }
@@ -0,0 +1,239 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+
+use crate::{temp::TempFile, Content, ContentMap, RenpyArchive, RpaError, RpaResult};
+
+impl<R> RenpyArchive<R>
+where
+    R: Seek + BufRead,
+{
+    /// Consume and atomically write the archive to the file at `path`, returning
+    /// the saved archive read from `path`.
+    ///
+    /// The archive is written with [`RenpyArchive::flush_with`] to a uniquely named temp
+    /// file in the same directory, which then replaces `path`. The file and its
+    /// directory are synced to disk, so `path` holds either the previous or the new
+    /// archive even after a crash or power loss. The permissions of an existing file
+    /// are kept and a symlink at `path` has its target replaced.
+    ///
+    /// Replacing the archive the content is read from is safe, as readers keep the
+    /// previous file open. The returned archive is bound to the new file and its
+    /// index is the one just written, so it can be modified and saved again without
+    /// reloading it.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `ReadsTarget` if `File` content is read from `path`,
+    /// in which case nothing is written, and any error raised while writing. The temp
+    /// file is removed on errors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::RenpyArchive;
+    ///
+    /// let path = std::env::temp_dir().join("warpalib-save-example.rpa");
+    ///
+    /// let mut archive = RenpyArchive::new();
+    /// archive.content.insert_raw("a.txt", b"hello".to_vec());
+    /// archive.save_atomic(&path).expect("Failed to save archive");
+    ///
+    /// // Replace the archive with itself and a new file.
+    /// let mut archive = RenpyArchive::open(&path).unwrap();
+    /// archive.content.insert_raw("b.txt", b"world".to_vec());
+    /// let mut archive = archive.save_atomic(&path).expect("Failed to save archive");
+    ///
+    /// // The saved archive keeps reading from the new file.
+    /// let mut data = vec![];
+    /// archive.copy_file("a.txt", &mut data).unwrap();
+    /// assert_eq!(data, b"hello");
+    ///
+    /// archive.content.remove("b.txt");
+    /// let archive = archive.save_atomic(&path).expect("Failed to save archive");
+    /// assert_eq!(archive.content.len(), 1);
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn save_atomic(self, path: &Path) -> RpaResult<RenpyArchive<BufReader<File>>> {
+        let (key, version) = (self.key, self.version.clone());
+        let written = self.save_atomic_with(path, |archive, file| {
+            archive.flush_with(file, &Default::default())
+        })?;
+
+        let reader = BufReader::new(File::open(path)?);
+        RenpyArchive::from_written(reader, key, version, written)
+    }
+
+    /// Consume and atomically write the archive to the file at `path` using `write`.
+    ///
+    /// This behaves like [`RenpyArchive::save_atomic`], with `write` writing the
+    /// archive to the temp file, for example with [`RenpyArchive::flush_with`] or
+    /// [`RenpyArchive::flush_parallel`]. Returns the value returned by `write`, such
+    /// as the index written by those.
+    pub fn save_atomic_with<T, F>(self, path: &Path, write: F) -> RpaResult<T>
+    where
+        F: FnOnce(Self, &mut File) -> RpaResult<T>,
+    {
+        let target = resolve_target(path)?;
+        check_sources(&self.content, &target)?;
+
+        let written = write_atomic(&target, |file| write(self, file))?;
+        debug!("Saved archive to {}", target.display());
+        Ok(written)
+    }
+}
+
+/// The file replaced when writing to `path`, the target of a symlink rather than the
+/// link itself.
+pub(crate) fn resolve_target(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(target) => Ok(target),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(path.to_path_buf()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Atomically replace the file at `target` with the file written by `write`.
+///
+/// The file is written to a temp file next to `target`, keeping its permissions, and
+/// synced to disk along with its directory once moved over `target`.
+pub(crate) fn write_atomic<T, F>(target: &Path, write: F) -> RpaResult<T>
+where
+    F: FnOnce(&mut File) -> RpaResult<T>,
+{
+    let permissions = match fs::metadata(target) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    // The file is dropped before the temp file removing it on errors.
+    let (temp, mut file) = TempFile::create(target)?;
+    trace!("Writing to temp file {}", temp.path().display());
+    let written = write(&mut file)?;
+
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    temp.persist(target)?;
+    sync_parent(target)?;
+
+    Ok(written)
+}
+
+/// Return `ReadsTarget` if `File` content of the `content` is the file at `target`.
+fn check_sources(content: &ContentMap, target: &Path) -> RpaResult<()> {
+    if !target.exists() {
+        return Ok(());
+    }
+
+    for content in content.values() {
+        if let Content::File(path) = content {
+            if fs::canonicalize(path).is_ok_and(|path| path == target) {
+                return Err(RpaError::ReadsTarget(target.to_path_buf()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sync the directory containing `path`, making a rename into it durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Directories cannot be opened and synced on this platform, where renames are
+/// expected to be durable once they return.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("warpalib-save-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn archive() -> RenpyArchive<io::Cursor<Vec<u8>>> {
+        let mut archive = RenpyArchive::new();
+        archive.content.insert_raw("a.txt", b"hello".to_vec());
+        archive
+    }
+
+    #[test]
+    fn should_not_write_archives_reading_the_target() {
+        let dir = test_dir("reads-target");
+        let path = dir.join("a.rpa");
+        fs::write(&path, b"not an archive").unwrap();
+
+        let mut archive = archive();
+        archive
+            .content
+            .insert_file_mapped("b.rpa", dir.join(".").join("a.rpa"));
+        let result = archive.save_atomic(&path);
+
+        assert!(
+            matches!(result, Err(RpaError::ReadsTarget(target)) if target == path.canonicalize().unwrap())
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"not an archive");
+        // The temp file is only created once the sources are checked.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_keep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("permissions");
+        let path = dir.join("a.rpa");
+        archive().save_atomic(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let archive = archive().save_atomic(&path).unwrap();
+        assert_eq!(archive.content.len(), 1);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_remove_temp_file_on_errors() {
+        let dir = test_dir("errors");
+        let path = dir.join("a.rpa");
+        fs::write(&path, b"previous").unwrap();
+
+        let result = archive().save_atomic_with(&path, |_, file| {
+            io::Write::write_all(file, b"partial")?;
+            Err::<(), _>(RpaError::Cancelled)
+        });
+
+        assert!(matches!(result, Err(RpaError::Cancelled)));
+        assert_eq!(fs::read(&path).unwrap(), b"previous");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
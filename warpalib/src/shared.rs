@@ -0,0 +1,209 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    extract::{extract_parallel, select},
+    ArchivePath, ContentMap, ExtractOptions, ExtractSummary, Hooks, RenpyArchive, RpaError,
+    RpaResult, RpaVersion,
+};
+
+/// Size of the buffer of each thread extracting from the archive file.
+const READ_BUFFER: usize = 1 << 16;
+
+/// A renpy archive file that can be read from multiple threads at once.
+///
+/// Unlike [`RenpyArchive`], which owns a single stateful reader, data is read
+/// with positional reads on the shared file. Each reader created with
+/// [`SharedArchive::reader`] keeps its own position, so entries can be copied
+/// concurrently through a shared reference.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::{fs::File, thread};
+/// use warpalib::{RenpyArchive, SharedArchive};
+///
+/// let path = std::env::temp_dir().join("warpalib-shared-example.rpa");
+///
+/// let mut archive = RenpyArchive::new();
+/// archive.content.insert_raw("a.txt", b"hello".to_vec());
+/// archive.content.insert_raw("b.txt", b"world".to_vec());
+/// archive.flush(&mut File::create(&path).unwrap()).unwrap();
+///
+/// let archive = SharedArchive::open(&path).expect("Failed to open archive");
+/// thread::scope(|scope| {
+///     for (path, expected) in [("a.txt", b"hello"), ("b.txt", b"world")] {
+///         let archive = &archive;
+///         scope.spawn(move || {
+///             let mut data = vec![];
+///             archive.copy_file(path, &mut data).unwrap();
+///             assert_eq!(&data, expected);
+///         });
+///     }
+/// });
+/// # std::fs::remove_file(path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SharedArchive {
+    file: File,
+    length: u64,
+
+    /// Key used to encode and decode index locations.
+    pub key: Option<u64>,
+
+    /// The offset where index data is stored.
+    pub offset: u64,
+
+    /// The version of this archive.
+    pub version: RpaVersion,
+
+    /// The content present in this archive.
+    pub content: ContentMap,
+}
+
+impl SharedArchive {
+    /// Open archive from file.
+    pub fn open(path: &Path) -> RpaResult<Self> {
+        RenpyArchive::open(path)?.try_into()
+    }
+
+    /// Return a reader over the archive file with its own position.
+    ///
+    /// The reader can be passed to [`Content::copy_to`](crate::Content::copy_to)
+    /// to copy the content of this archive.
+    pub fn reader(&self) -> PositionalReader<'_> {
+        PositionalReader::new(&self.file, self.length)
+    }
+
+    /// Copy content from a file in the archive to the `writer`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `NotFound` error if `path` is not present in
+    /// the archive and any errors raised during the copy process.
+    pub fn copy_file<P, W>(&self, path: P, writer: &mut W) -> RpaResult<u64>
+    where
+        P: Into<ArchivePath>,
+        W: Write,
+    {
+        self.copy_file_with(path, writer, &Hooks::default())
+    }
+
+    /// Copy content from a file in the archive to the `writer`, reporting progress
+    /// to and checking cancellation of the `hooks`.
+    ///
+    /// See [`RenpyArchive::copy_file_with`].
+    pub fn copy_file_with<P, W>(&self, path: P, writer: &mut W, hooks: &Hooks) -> RpaResult<u64>
+    where
+        P: Into<ArchivePath>,
+        W: Write,
+    {
+        let path = path.into();
+        match self.content.get(&path) {
+            Some(content) => {
+                hooks.started([content].into_iter());
+                hooks.copy(&path, content, &mut self.reader(), writer)
+            }
+            None => Err(RpaError::NotFound(path)),
+        }
+    }
+
+    /// Extract the content of the archive into `dir` on multiple threads.
+    ///
+    /// This behaves like [`RenpyArchive::extract_to`], with `options.threads` threads
+    /// each reading a contiguous part of the archive. On Linux, records are copied
+    /// within the kernel using `copy_file_range`.
+    pub fn extract_to(&self, dir: &Path, options: &ExtractOptions) -> ExtractSummary {
+        let entries = select(&self.content, options);
+        extract_parallel(
+            || BufReader::with_capacity(READ_BUFFER, self.reader()),
+            Some(&self.file),
+            dir,
+            &entries,
+            options,
+        )
+    }
+}
+
+impl TryFrom<RenpyArchive<BufReader<File>>> for SharedArchive {
+    type Error = RpaError;
+
+    /// Share the file of an archive opened with [`RenpyArchive::open`].
+    fn try_from(archive: RenpyArchive<BufReader<File>>) -> RpaResult<Self> {
+        let file = archive.reader.into_inner();
+        let length = file.metadata()?.len();
+
+        Ok(Self {
+            file,
+            length,
+            key: archive.key,
+            offset: archive.offset,
+            version: archive.version,
+            content: archive.content,
+        })
+    }
+}
+
+/// A reader over a [`SharedArchive`] file using positional reads.
+///
+/// Created using [`SharedArchive::reader`].
+#[derive(Debug)]
+pub struct PositionalReader<'a> {
+    file: &'a File,
+    length: u64,
+    position: u64,
+}
+
+impl<'a> PositionalReader<'a> {
+    /// Create a reader at the start of the `file` of `length` bytes.
+    pub(crate) fn new(file: &'a File, length: u64) -> Self {
+        Self {
+            file,
+            length,
+            position: 0,
+        }
+    }
+}
+
+impl Read for PositionalReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = read_at(self.file, buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for PositionalReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    // Moves the file cursor, which is never used otherwise.
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
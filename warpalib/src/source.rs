@@ -0,0 +1,120 @@
+use std::{
+    fmt::Debug,
+    io::{self, Read},
+    sync::Mutex,
+};
+
+/// A source of data that is read when the archive is written.
+///
+/// Implement this to avoid buffering generated data into memory or writing it
+/// to a temporary file before adding it to an archive.
+pub trait ContentSource: Send + Sync {
+    /// Open a reader over the data of this source.
+    ///
+    /// This is called each time the data is copied.
+    fn open(&self) -> io::Result<Box<dyn Read + '_>>;
+
+    /// The number of bytes the source produces, if known in advance.
+    fn length(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A source that creates a new reader using a function each time it is opened.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use warpalib::{ContentMap, ReaderFn};
+///
+/// let mut map = ContentMap::default();
+/// let source = ReaderFn::new(|| Ok(Cursor::new(vec![0u8; 16]))).with_length(16);
+/// map.insert_source("zeros.bin", source);
+/// ```
+pub struct ReaderFn<F> {
+    factory: F,
+    length: Option<u64>,
+}
+
+impl<F> ReaderFn<F> {
+    /// Create a source from a function returning a reader.
+    pub fn new(factory: F) -> Self {
+        Self {
+            factory,
+            length: None,
+        }
+    }
+
+    /// Set the number of bytes produced by the readers.
+    pub fn with_length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+}
+
+impl<F, R> ContentSource for ReaderFn<F>
+where
+    F: Fn() -> io::Result<R> + Send + Sync,
+    R: Read + 'static,
+{
+    fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new((self.factory)()?))
+    }
+
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+}
+
+/// A source wrapping a reader that can only be opened once, such as stdin or
+/// a network stream.
+///
+/// Opening the source a second time returns an error.
+pub struct ReadOnce {
+    reader: Mutex<Option<Box<dyn Read + Send>>>,
+    length: Option<u64>,
+}
+
+impl ReadOnce {
+    /// Create a source from a reader.
+    pub fn new<R: Read + Send + 'static>(reader: R) -> Self {
+        Self {
+            reader: Mutex::new(Some(Box::new(reader))),
+            length: None,
+        }
+    }
+
+    /// Set the number of bytes produced by the reader.
+    pub fn with_length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+}
+
+impl ContentSource for ReadOnce {
+    fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+        let reader = self
+            .reader
+            .lock()
+            .map_err(|_| io::Error::other("source lock poisoned"))?
+            .take();
+
+        match reader {
+            Some(reader) => Ok(reader),
+            None => Err(io::Error::other("source can only be read once")),
+        }
+    }
+
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+}
+
+impl Debug for dyn ContentSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentSource")
+            .field("length", &self.length())
+            .finish()
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::HashMap, io};
+
+use log::debug;
+
+use crate::{
+    write_index, ArchivePath, Content, ContentMap, Record, RpaError, RpaResult, RpaVersion,
+};
+
+impl ContentMap {
+    /// Partition the content into volumes that each fit into `max_size` bytes when
+    /// written as an archive of `version` obfuscated with `key`.
+    ///
+    /// The size of a volume is exact: it includes the archive header and the index as
+    /// written by [`RenpyArchive::flush`]. Content is never split across volumes and
+    /// is packed in path order, so the result is deterministic.
+    ///
+    /// Take care to detach the map using [`ContentMap::into_external`] if it contains
+    /// records, as each volume is written as a separate archive.
+    ///
+    /// [`RenpyArchive::flush`]: crate::RenpyArchive::flush
+    ///
+    /// # Errors
+    ///
+    /// This function returns `VolumeExceeded` if any content does not fit into a single
+    /// volume, and any errors raised while reading the length of the content.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use warpalib::{ContentMap, RpaVersion};
+    ///
+    /// let mut map = ContentMap::default();
+    /// map.insert_raw("a.txt", vec![0u8; 600]);
+    /// map.insert_raw("b.txt", vec![0u8; 600]);
+    /// map.insert_raw("c.txt", vec![0u8; 200]);
+    ///
+    /// let volumes = map
+    ///     .split_by_size(1024, &RpaVersion::V3_0, Some(0xDEADBEEF))
+    ///     .unwrap();
+    /// assert_eq!(volumes.len(), 2);
+    /// ```
+    pub fn split_by_size(
+        self,
+        max_size: u64,
+        version: &RpaVersion,
+        key: Option<u64>,
+    ) -> RpaResult<Vec<ContentMap>> {
+        let header_length = version.header_length()? as u64;
+
+        let mut pending = Vec::with_capacity(self.len());
+        for (path, content) in self {
+            let length = content.length()?;
+            pending.push((path, content, length));
+        }
+        pending.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+        // Find where each volume ends before moving the content into them.
+        let mut counts = Vec::new();
+        let mut start = 0;
+        while start < pending.len() {
+            let count = volume_length(&pending[start..], max_size, header_length, key)?;
+            debug!("Volume {} filled with {count} entries", counts.len());
+            counts.push(count);
+            start += count;
+        }
+
+        let mut pending = pending.into_iter();
+        Ok(counts
+            .into_iter()
+            .map(|count| {
+                let volume = pending
+                    .by_ref()
+                    .take(count)
+                    .map(|(path, content, _)| (path, content))
+                    .collect::<HashMap<_, _>>();
+                ContentMap::from(volume)
+            })
+            .collect())
+    }
+}
+
+/// The number of leading `entries` fitting into a volume of `max_size` bytes.
+///
+/// The data alone bounds the entries that can fit, as the index only adds to it.
+/// Within that bound, the entries are doubled until they no longer fit and the cut
+/// point is binary searched, so the index is written a logarithmic number of times
+/// per volume and never for many more entries than it holds.
+///
+/// # Errors
+///
+/// This function returns `VolumeExceeded` if not even the first entry fits.
+fn volume_length(
+    entries: &[(ArchivePath, Content, u64)],
+    max_size: u64,
+    header_length: u64,
+    key: Option<u64>,
+) -> RpaResult<usize> {
+    let mut data = header_length;
+    let most = entries
+        .iter()
+        .take_while(|(.., length)| {
+            data += length;
+            data <= max_size
+        })
+        .count();
+
+    let fit =
+        |count| Ok::<_, RpaError>(volume_size(&entries[..count], header_length, key)? <= max_size);
+
+    // The size of a volume grows with the entries added to it, bar small dips in the
+    // compressed index. Every cut is measured, so volumes fit either way.
+    let (mut fits, mut exceeds) = (0, most + 1);
+    while fits < most {
+        let count = (fits * 2).clamp(1, most);
+        match fit(count)? {
+            true => fits = count,
+            false => {
+                exceeds = count;
+                break;
+            }
+        }
+    }
+    while exceeds - fits > 1 {
+        let count = fits + (exceeds - fits) / 2;
+        match fit(count)? {
+            true => fits = count,
+            false => exceeds = count,
+        }
+    }
+
+    match fits {
+        0 => Err(RpaError::VolumeExceeded(entries[0].0.clone(), max_size)),
+        fits => Ok(fits),
+    }
+}
+
+/// The size of the `entries` when written as an archive, laying out the data in the
+/// order used by [`RenpyArchive::flush`](crate::RenpyArchive::flush).
+fn volume_size(
+    entries: &[(ArchivePath, Content, u64)],
+    header_length: u64,
+    key: Option<u64>,
+) -> RpaResult<u64> {
+    let mut order = entries.iter().collect::<Vec<_>>();
+    order.sort_by(|(a, x, _), (b, y, _)| x.cmp_offset(y).then_with(|| a.cmp(b)));
+
+    let mut offset = header_length;
+    let mut indexes = Vec::with_capacity(order.len());
+    for (path, _, length) in order {
+        indexes.push((path.to_string(), Record::new(offset, *length, None, None)));
+        offset += length;
+    }
+
+    Ok(offset + write_index(&mut io::sink(), indexes, key)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::RenpyArchive;
+
+    use super::*;
+
+    fn written_size(volume: ContentMap, version: RpaVersion, key: Option<u64>) -> u64 {
+        let mut archive = RenpyArchive::new();
+        archive.version = version;
+        archive.key = key;
+        archive.content = volume;
+
+        let mut buffer = Cursor::new(vec![]);
+        archive.flush(&mut buffer).unwrap();
+        buffer.into_inner().len() as u64
+    }
+
+    #[test]
+    fn volumes_should_fit_max_size_when_written() {
+        // Many small files with long paths, where the index dominates the size.
+        let mut map = ContentMap::default();
+        for i in 0..500 {
+            let path = format!("images/characters/eileen/expression_{i:04}_happy.png");
+            map.insert_raw(path, vec![(i % 256) as u8; i % 7]);
+        }
+
+        let max_size = 2048;
+        let volumes = map
+            .split_by_size(max_size, &RpaVersion::V3_0, Some(0xDEADBEEF))
+            .unwrap();
+        assert!(volumes.len() > 1);
+        assert_eq!(volumes.iter().map(|v| v.len()).sum::<usize>(), 500);
+
+        for volume in volumes {
+            let size = written_size(volume, RpaVersion::V3_0, Some(0xDEADBEEF));
+            assert!(size <= max_size);
+        }
+    }
+
+    #[test]
+    fn should_split_many_entries_into_full_volumes() {
+        let mut map = ContentMap::default();
+        for i in 0..5_000 {
+            map.insert_raw(format!("images/{i:05}.png"), vec![(i % 256) as u8; 16]);
+        }
+
+        let max_size = 16 << 10;
+        let volumes = map
+            .split_by_size(max_size, &RpaVersion::V3_0, Some(0xDEADBEEF))
+            .unwrap();
+        assert!(volumes.len() > 2);
+        assert_eq!(volumes.iter().map(|v| v.len()).sum::<usize>(), 5_000);
+
+        // Copy the raw content of a volume to write it.
+        let copy = |volume: &ContentMap| {
+            let mut copy = ContentMap::default();
+            for (path, content) in volume.iter() {
+                match content {
+                    Content::Raw(data) => copy.insert_raw(path.clone(), data.clone()),
+                    _ => unreachable!("only raw content was inserted"),
+                };
+            }
+            copy
+        };
+
+        let mut volumes = volumes.into_iter().peekable();
+        while let Some(volume) = volumes.next() {
+            let size = written_size(copy(&volume), RpaVersion::V3_0, Some(0xDEADBEEF));
+            assert!(size <= max_size);
+
+            // The first entry of the next volume does not fit into this one.
+            if let Some(next) = volumes.peek() {
+                let first = next.keys().min().unwrap();
+                let mut grown = copy(&volume);
+                grown.insert_raw(first.clone(), vec![0u8; 16]);
+                let grown = written_size(grown, RpaVersion::V3_0, Some(0xDEADBEEF));
+                assert!(grown > max_size);
+            }
+        }
+    }
+
+    #[test]
+    fn volumes_should_be_filled_exactly() {
+        let map = || {
+            let mut map = ContentMap::default();
+            map.insert_raw("a.txt", vec![0u8; 100]);
+            map.insert_raw("b.txt", vec![1u8; 100]);
+            map
+        };
+        let exact = written_size(map(), RpaVersion::V2_0, None);
+
+        let volumes = map().split_by_size(exact, &RpaVersion::V2_0, None).unwrap();
+        assert_eq!(volumes.len(), 1);
+
+        let volumes = map()
+            .split_by_size(exact - 1, &RpaVersion::V2_0, None)
+            .unwrap();
+        assert_eq!(volumes.len(), 2);
+    }
+
+    #[test]
+    fn content_larger_than_volume_should_fail() {
+        let mut map = ContentMap::default();
+        map.insert_raw("a.txt", vec![0u8; 10]);
+        map.insert_raw("b.txt", vec![0u8; 2000]);
+
+        let result = map.split_by_size(1024, &RpaVersion::V3_0, None);
+        assert!(matches!(
+            result,
+            Err(RpaError::VolumeExceeded(path, 1024)) if path == ArchivePath::from("b.txt")
+        ));
+    }
+
+    #[test]
+    fn volumes_should_be_packed_in_path_order() {
+        let mut map = ContentMap::default();
+        for name in ["d", "b", "a", "c"] {
+            map.insert_raw(name, vec![0u8; 300]);
+        }
+
+        let volumes = map.split_by_size(800, &RpaVersion::V3_0, None).unwrap();
+        let paths = volumes
+            .iter()
+            .map(|volume| {
+                let mut paths = volume.keys().map(|p| p.as_str()).collect::<Vec<_>>();
+                paths.sort();
+                paths
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [["a", "b"], ["c", "d"]]);
+    }
+}
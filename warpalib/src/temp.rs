@@ -0,0 +1,87 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+/// Counter making temp file names unique within the process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A uniquely named temp file, removed when dropped unless persisted.
+#[derive(Debug)]
+pub(crate) struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    /// Create a uniquely named temp file in the directory of `target`, named after it.
+    pub(crate) fn create(target: &Path) -> io::Result<(Self, File)> {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+
+        loop {
+            let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path =
+                target.with_file_name(format!(".{name}.{}.{nanos:x}{count}.tmp", process::id()));
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    let temp = Self {
+                        path,
+                        persisted: false,
+                    };
+                    return Ok((temp, file));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Create the empty file at `path`, failing with `AlreadyExists` if it exists.
+    ///
+    /// Used to claim a path before writing it, the file is removed when dropped unless
+    /// it is replaced or kept.
+    pub(crate) fn create_new(path: &Path) -> io::Result<Self> {
+        OpenOptions::new().write(true).create_new(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            persisted: false,
+        })
+    }
+
+    /// The path of the temp file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the file when dropped.
+    pub(crate) fn keep(mut self) {
+        self.persisted = true;
+    }
+
+    /// Move the file to `target`, replacing it, and keep it.
+    pub(crate) fn persist(mut self, target: &Path) -> io::Result<()> {
+        fs::rename(&self.path, target)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!("Failed to remove temp file {}: {e}", self.path.display());
+            }
+        }
+    }
+}